@@ -0,0 +1,312 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+
+use http::Request;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::ApiKeyConfig;
+use crate::metrics;
+
+const API_KEY_HEADER: &str = "x-api-key";
+const API_KEY_QUERY_PARAM: &str = "api_key";
+
+/// Methods which are allowed only for the admin keys
+const ADMIN_METHODS: &[&str] = &["reloadConfig"];
+
+/// Registered API keys. Authentication is disabled if there are no keys
+pub struct ApiKeys {
+    keys: HashMap<String, Arc<ApiKey>>,
+}
+
+impl ApiKeys {
+    pub fn new(configs: &[ApiKeyConfig]) -> Self {
+        Self {
+            keys: configs
+                .iter()
+                .map(|config| (config.key.clone(), Arc::new(ApiKey::new(config))))
+                .collect(),
+        }
+    }
+
+    /// Finds the key of the request. Returns `None` if authentication is disabled
+    pub fn authenticate<B>(&self, request: &Request<B>) -> Result<Option<Arc<ApiKey>>, AuthError> {
+        if self.keys.is_empty() {
+            return Ok(None);
+        }
+
+        let key = extract_api_key(request).ok_or(AuthError::MissingApiKey)?;
+        match self.keys.get(&key) {
+            Some(api_key) => Ok(Some(api_key.clone())),
+            None => Err(AuthError::InvalidApiKey),
+        }
+    }
+}
+
+pub struct ApiKey {
+    name: String,
+    allowed_methods: HashSet<String>,
+    max_subscriptions: usize,
+    admin: bool,
+    rate_limiter: parking_lot::Mutex<RateLimiter>,
+    concurrency: Arc<Semaphore>,
+}
+
+impl ApiKey {
+    fn new(config: &ApiKeyConfig) -> Self {
+        Self {
+            name: config.name.clone(),
+            allowed_methods: config.allowed_methods.iter().cloned().collect(),
+            max_subscriptions: config.max_subscriptions,
+            admin: config.admin,
+            rate_limiter: parking_lot::Mutex::new(RateLimiter::new(config.requests_per_second)),
+            concurrency: Arc::new(Semaphore::new(config.max_concurrent_requests)),
+        }
+    }
+
+    pub fn max_subscriptions(&self) -> usize {
+        self.max_subscriptions
+    }
+
+    /// Checks the quotas for the method call. The permit must be held until the call is finished
+    pub fn acquire(&self, method: Option<&str>) -> Result<OwnedSemaphorePermit, AuthError> {
+        let result = self.check_method(method).and_then(|_| {
+            self.concurrency
+                .clone()
+                .try_acquire_owned()
+                .map_err(|_| AuthError::TooManyConcurrentRequests)
+        });
+        self.record(result)
+    }
+
+    /// Same as `acquire`, but waits for a concurrency slot, so that calls
+    /// of one batch request don't reject each other
+    pub async fn acquire_batch_call(
+        &self,
+        method: Option<&str>,
+    ) -> Result<OwnedSemaphorePermit, AuthError> {
+        let result = match self.check_method(method) {
+            Ok(()) => self
+                .concurrency
+                .clone()
+                .acquire_owned()
+                .await
+                .map_err(|_| AuthError::TooManyConcurrentRequests),
+            Err(error) => Err(error),
+        };
+        self.record(result)
+    }
+
+    /// Checks that the batch request can get a concurrency slot
+    pub fn check_batch(&self) -> Result<(), AuthError> {
+        if self.concurrency.available_permits() > 0 {
+            return Ok(());
+        }
+        let error = AuthError::TooManyConcurrentRequests;
+        metrics::record_api_key_request(&self.name, Some(error.name()));
+        Err(error)
+    }
+
+    fn check_method(&self, method: Option<&str>) -> Result<(), AuthError> {
+        if let Some(method) = method {
+            if is_admin_method(method) && !self.admin {
+                return Err(AuthError::MethodNotAllowed);
+            }
+            if !self.allowed_methods.is_empty() && !self.allowed_methods.contains(method) {
+                return Err(AuthError::MethodNotAllowed);
+            }
+        }
+
+        if !self.rate_limiter.lock().try_acquire() {
+            return Err(AuthError::RateLimitExceeded);
+        }
+        Ok(())
+    }
+
+    fn record<T>(&self, result: Result<T, AuthError>) -> Result<T, AuthError> {
+        metrics::record_api_key_request(&self.name, result.as_ref().err().map(AuthError::name));
+        result
+    }
+}
+
+pub fn is_admin_method(method: &str) -> bool {
+    ADMIN_METHODS.contains(&method)
+}
+
+/// Max number of WebSocket subscriptions, passed to the handler via request extensions
+#[derive(Debug, Copy, Clone)]
+pub struct SubscriptionsLimit(pub usize);
+
+#[derive(thiserror::Error, Copy, Clone, Debug)]
+pub enum AuthError {
+    #[error("API key is required")]
+    MissingApiKey,
+    #[error("Invalid API key")]
+    InvalidApiKey,
+    #[error("Method is not allowed for this API key")]
+    MethodNotAllowed,
+    #[error("Rate limit exceeded")]
+    RateLimitExceeded,
+    #[error("Too many concurrent requests")]
+    TooManyConcurrentRequests,
+}
+
+impl AuthError {
+    pub fn code(&self) -> i64 {
+        match self {
+            AuthError::MissingApiKey => -32020,
+            AuthError::InvalidApiKey => -32021,
+            AuthError::MethodNotAllowed => -32022,
+            AuthError::RateLimitExceeded => -32023,
+            AuthError::TooManyConcurrentRequests => -32024,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuthError::MissingApiKey => "missing_api_key",
+            AuthError::InvalidApiKey => "invalid_api_key",
+            AuthError::MethodNotAllowed => "method_not_allowed",
+            AuthError::RateLimitExceeded => "rate_limit_exceeded",
+            AuthError::TooManyConcurrentRequests => "too_many_concurrent_requests",
+        }
+    }
+}
+
+impl From<AuthError> for warp_json_rpc::Error {
+    fn from(error: AuthError) -> Self {
+        warp_json_rpc::Error::custom(error.code(), error.to_string())
+    }
+}
+
+/// Token bucket which allows short bursts up to the one second limit
+struct RateLimiter {
+    requests_per_second: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: u32) -> Self {
+        let requests_per_second = requests_per_second as f64;
+        Self {
+            requests_per_second,
+            tokens: requests_per_second,
+            updated_at: Instant::now(),
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        self.updated_at = now;
+        self.tokens =
+            (self.tokens + elapsed * self.requests_per_second).min(self.requests_per_second);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn extract_api_key<B>(request: &Request<B>) -> Option<String> {
+    if let Some(key) = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        return Some(key.to_owned());
+    }
+
+    let query = request.uri().query()?;
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(name, _)| name == API_KEY_QUERY_PARAM)
+        .map(|(_, value)| value.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn api_key(requests_per_second: u32, max_concurrent_requests: usize) -> ApiKey {
+        ApiKey::new(&ApiKeyConfig {
+            name: "test".to_owned(),
+            key: "key".to_owned(),
+            requests_per_second,
+            max_concurrent_requests,
+            max_subscriptions: 1,
+            allowed_methods: vec!["getShards".to_owned()],
+            admin: false,
+        })
+    }
+
+    #[test]
+    fn rate_limiter_allows_burst() {
+        let mut limiter = RateLimiter::new(3);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn rate_limiter_refills() {
+        let mut limiter = RateLimiter::new(2);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+
+        limiter.updated_at -= Duration::from_millis(500);
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+
+        // Tokens are not accumulated above the one second limit
+        limiter.updated_at -= Duration::from_secs(10);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn method_restrictions() {
+        let api_key = api_key(100, 10);
+        assert!(api_key.acquire(Some("getShards")).is_ok());
+        assert!(matches!(
+            api_key.acquire(Some("getTransactions")),
+            Err(AuthError::MethodNotAllowed)
+        ));
+        assert!(matches!(
+            api_key.acquire(Some("reloadConfig")),
+            Err(AuthError::MethodNotAllowed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn batch_calls_wait_for_permits() {
+        let api_key = api_key(100, 1);
+        let permit = api_key.acquire(Some("getShards")).unwrap();
+
+        assert!(matches!(
+            api_key.acquire(Some("getShards")),
+            Err(AuthError::TooManyConcurrentRequests)
+        ));
+        assert!(matches!(
+            api_key.check_batch(),
+            Err(AuthError::TooManyConcurrentRequests)
+        ));
+
+        let call = api_key.acquire_batch_call(Some("getShards"));
+        tokio::pin!(call);
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut call)
+            .await
+            .is_err());
+
+        drop(permit);
+        assert!(call.await.is_ok());
+    }
+}
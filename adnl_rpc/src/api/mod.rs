@@ -1,22 +1,41 @@
 use std::convert::Infallible;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use futures::future;
 use http::Response;
 use hyper::Body;
 use serde::Serialize;
+use tokio::signal;
 use warp::filters::BoxedFilter;
 use warp::http::StatusCode;
 use warp::{Filter, Rejection};
 use warp_json_rpc::filters as json_rpc;
 
-use adnl_rpc_models::{GetContractState, GetTransactions, SendMessage};
+use adnl_rpc_models::{
+    GetBlockTransactions, GetConfigParams, GetContractState, GetTransaction, GetTransactions,
+    LookupBlockByLt, LookupBlockBySeqno, LookupBlockByUtime, RunGetMethod, RunLocal, SendMessage,
+    SendMessageAndWait,
+};
 
 use crate::config::Config;
+use crate::metrics;
 use crate::ton::*;
 
+use self::auth::{ApiKeys, SubscriptionsLimit};
+pub use self::reload::ConfigLoader;
+use self::reload::ConfigReloader;
+use self::tls::TlsAcceptor;
+
+mod auth;
+mod reload;
+mod service;
+mod tls;
+
 const RPC_API_PATH: &str = "rpc";
+const STREAM_API_PATH: &str = "stream";
 
 // This is a workaround for not being able to create a `warp_json_rpc::Response` without a
 // `warp_json_rpc::Builder`.
@@ -42,25 +61,108 @@ fn new_error_response(error: warp_json_rpc::Error) -> Response<Body> {
         .unwrap()
 }
 
-pub async fn serve(config: Config) -> Result<()> {
+pub async fn serve(
+    config: Config,
+    logger: log4rs::Handle,
+    load_config: ConfigLoader,
+) -> Result<()> {
     let address = config.listen_address;
-    let state = Arc::new(State::new(config).await?);
+    let tls_config = config.tls.clone();
+    let shutdown_timeout = config.shutdown_timeout;
+    let api_keys = Arc::new(ApiKeys::new(&config.api_keys));
+    let state = Arc::new(State::new(config.clone()).await?);
 
     state.start_masterchain_cache_updater();
+    state.start_block_notifier();
+    state.start_indexer();
+
+    let reloader = Arc::new(ConfigReloader::new(
+        state.clone(),
+        logger,
+        load_config,
+        config,
+    )?);
+    reloader.start_signal_listener();
 
-    let routes = rpc(state.clone()).or(ws_stream(state));
+    let routes = rpc(state.clone(), reloader)
+        .or(prometheus_metrics(state.clone()))
+        .or(ws_stream(state.clone()));
 
     let service = warp_json_rpc::service(routes);
-    log::info!("Started server");
-    hyper::Server::bind(&address)
-        .serve(hyper::service::make_service_fn(move |_| {
-            future::ok::<_, Infallible>(service.clone())
-        }))
-        .await?;
+    let handler =
+        move |request| service::handle_request(service.clone(), api_keys.clone(), request);
+    let make_service = move || {
+        let handler = handler.clone();
+        future::ok::<_, Infallible>(hyper::service::service_fn(handler))
+    };
+
+    match tls_config {
+        Some(tls_config) => {
+            let acceptor = Arc::new(TlsAcceptor::new(tls_config)?);
+            acceptor.start_reloader();
+
+            let listener = tokio::net::TcpListener::bind(address).await?;
+            log::info!("Started server with TLS");
+            let incoming = tls::incoming(listener, acceptor, {
+                let state = state.clone();
+                async move { state.wait_for_shutdown().await }
+            });
+            let server = hyper::Server::builder(incoming)
+                .serve(hyper::service::make_service_fn(move |_| make_service()))
+                .with_graceful_shutdown(shutdown_signal(state.clone()));
+            drain(server, &state, shutdown_timeout).await?;
+        }
+        None => {
+            log::info!("Started server");
+            let server = hyper::Server::bind(&address)
+                .serve(hyper::service::make_service_fn(move |_| make_service()))
+                .with_graceful_shutdown(shutdown_signal(state.clone()));
+            drain(server, &state, shutdown_timeout).await?;
+        }
+    }
+
+    state.close(shutdown_timeout).await;
     Ok(())
 }
 
-pub fn rpc(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+/// Stops accepting new connections on SIGTERM or SIGINT
+async fn shutdown_signal(state: Arc<State>) {
+    let mut terminate = match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            log::error!("Failed to listen for SIGTERM: {}", e);
+            return future::pending().await;
+        }
+    };
+
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = signal::ctrl_c() => {}
+    }
+
+    log::info!("Shutting down");
+    state.shutdown();
+}
+
+/// Runs the server until it is shut down. In-flight requests are dropped
+/// if they don't finish in time
+async fn drain<F>(server: F, state: &State, timeout: Duration) -> Result<()>
+where
+    F: Future<Output = hyper::Result<()>>,
+{
+    tokio::pin!(server);
+    tokio::select! {
+        result = &mut server => return result.map_err(From::from),
+        _ = state.wait_for_shutdown() => {}
+    }
+
+    if tokio::time::timeout(timeout, server).await.is_err() {
+        log::warn!("In-flight requests didn't finish in {:?}", timeout);
+    }
+    Ok(())
+}
+
+pub fn rpc(state: Arc<State>, reloader: Arc<ConfigReloader>) -> BoxedFilter<(impl warp::Reply,)> {
     let unknown_method = warp::path(RPC_API_PATH)
         .and(warp_json_rpc::filters::json_rpc())
         .and_then(move |response_builder: warp_json_rpc::Builder| async move {
@@ -79,9 +181,23 @@ pub fn rpc(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
 
     healthcheck(state.clone())
         .or(send_message(state.clone()))
+        .or(send_message_and_wait(state.clone()))
         .or(get_contract_state(state.clone()))
+        .or(run_get_method(state.clone()))
+        .or(run_local(state.clone()))
+        .or(emulate_message(state.clone()))
         .or(get_transactions(state.clone()))
-        .or(get_latest_key_block(state))
+        .or(get_decoded_transactions(state.clone()))
+        .or(get_transaction(state.clone()))
+        .or(get_masterchain_info(state.clone()))
+        .or(get_latest_key_block(state.clone()))
+        .or(get_config_params(state.clone()))
+        .or(get_shards(state.clone()))
+        .or(get_block_transactions(state.clone()))
+        .or(lookup_block_by_seqno(state.clone()))
+        .or(lookup_block_by_lt(state.clone()))
+        .or(lookup_block_by_utime(state))
+        .or(reload_config(reloader))
         .or(unknown_method)
         .or(parse_failure)
         .with(warp::compression::gzip())
@@ -89,10 +205,18 @@ pub fn rpc(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
 }
 
 #[allow(clippy::unnecessary_wraps)]
-fn wrap(
+async fn wrap<T>(
     res: warp_json_rpc::Builder,
-    result: QueryResult<impl serde::Serialize + 'static>,
-) -> Result<impl warp::Reply, Infallible> {
+    method: &'static str,
+    result: impl Future<Output = QueryResult<T>>,
+) -> Result<impl warp::Reply, Infallible>
+where
+    T: serde::Serialize + 'static,
+{
+    let started_at = Instant::now();
+    let result = result.await;
+    metrics::record_request(method, started_at.elapsed(), result.as_ref().err());
+
     Ok(match result {
         Ok(result) => res.success(result),
         Err(error) => res.error(warp_json_rpc::Error::custom(
@@ -108,14 +232,13 @@ pub fn healthcheck(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
         .map(move || state.clone())
         .and(warp::get())
         .map(|state: Arc<State>| {
-            warp::reply::with_status(
-                chrono::Utc::now().timestamp_millis().to_string(),
-                if state.is_ok() {
-                    http::StatusCode::OK
-                } else {
-                    http::StatusCode::SERVICE_UNAVAILABLE
-                },
-            )
+            let healthcheck = state.healthcheck();
+            let status = if healthcheck.ok {
+                http::StatusCode::OK
+            } else {
+                http::StatusCode::SERVICE_UNAVAILABLE
+            };
+            warp::reply::with_status(warp::reply::json(&healthcheck), status)
         })
         .boxed()
 }
@@ -129,11 +252,32 @@ pub fn send_message(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
         .and(json_rpc::method("sendMessage"))
         .and(json_rpc::params())
         .and_then(|state: Arc<State>, res, req: SendMessage| async move {
-            wrap(res, state.send_message(req.message).await)
+            wrap(res, "sendMessage", state.send_message(req.message)).await
         })
         .boxed()
 }
 
+pub fn send_message_and_wait(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("sendMessageAndWait");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("sendMessageAndWait"))
+        .and(json_rpc::params())
+        .and_then(
+            |state: Arc<State>, res, req: SendMessageAndWait| async move {
+                wrap(
+                    res,
+                    "sendMessageAndWait",
+                    state.send_message_and_wait(req.message, req.abi, req.expire_at),
+                )
+                .await
+            },
+        )
+        .boxed()
+}
+
 pub fn get_contract_state(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
     log::debug!("getContractState");
     warp::path(RPC_API_PATH)
@@ -143,7 +287,64 @@ pub fn get_contract_state(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)>
         .and(json_rpc::method("getContractState"))
         .and(json_rpc::params::<GetContractState>())
         .and_then(|state: Arc<State>, res, req: GetContractState| async move {
-            wrap(res, state.get_contract_state(req.address).await)
+            wrap(
+                res,
+                "getContractState",
+                state.get_contract_state(req.address),
+            )
+            .await
+        })
+        .boxed()
+}
+
+pub fn run_get_method(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("runGetMethod");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("runGetMethod"))
+        .and(json_rpc::params())
+        .and_then(|state: Arc<State>, res, req: RunGetMethod| async move {
+            wrap(
+                res,
+                "runGetMethod",
+                state.run_get_method(req.address, req.method, req.stack),
+            )
+            .await
+        })
+        .boxed()
+}
+
+pub fn run_local(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("runLocal");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("runLocal"))
+        .and(json_rpc::params())
+        .and_then(|state: Arc<State>, res, req: RunLocal| async move {
+            wrap(
+                res,
+                "runLocal",
+                state.run_local(req.address, req.abi, req.method, req.input),
+            )
+            .await
+        })
+        .boxed()
+}
+
+pub fn emulate_message(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("emulateMessage");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("emulateMessage"))
+        .and(json_rpc::params())
+        .and_then(|state: Arc<State>, res, req: SendMessage| async move {
+            wrap(res, "emulateMessage", state.emulate_message(req.message)).await
         })
         .boxed()
 }
@@ -159,10 +360,69 @@ pub fn get_transactions(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
         .and_then(|state: Arc<State>, res, req: GetTransactions| async move {
             wrap(
                 res,
-                state
-                    .get_transactions(req.address, req.transaction_id, req.count)
-                    .await,
+                "getTransactions",
+                state.get_transactions(req.address, req.transaction_id, req.count),
             )
+            .await
+        })
+        .boxed()
+}
+
+pub fn get_masterchain_info(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("getMasterchainInfo");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("getMasterchainInfo"))
+        .and_then(|state: Arc<State>, res| async move {
+            wrap(res, "getMasterchainInfo", state.get_masterchain_info()).await
+        })
+        .boxed()
+}
+
+pub fn get_decoded_transactions(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("getDecodedTransactions");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("getDecodedTransactions"))
+        .and(json_rpc::params())
+        .and_then(|state: Arc<State>, res, req: GetTransactions| async move {
+            wrap(
+                res,
+                "getDecodedTransactions",
+                state.get_decoded_transactions(req.address, req.transaction_id, req.count),
+            )
+            .await
+        })
+        .boxed()
+}
+
+pub fn get_transaction(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("getTransaction");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("getTransaction"))
+        .and(json_rpc::params())
+        .and_then(|state: Arc<State>, res, req: GetTransaction| async move {
+            let result = async {
+                match req {
+                    GetTransaction::ByBlock {
+                        address,
+                        lt,
+                        block_id,
+                    } => state.get_transaction_by_block(address, lt, block_id).await,
+                    GetTransaction::ById {
+                        address,
+                        transaction_id,
+                    } => state.get_transaction_by_id(address, transaction_id).await,
+                }
+            };
+            wrap(res, "getTransaction", result).await
         })
         .boxed()
 }
@@ -174,19 +434,188 @@ pub fn get_latest_key_block(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,
         .map(move || state.clone())
         .and(json_rpc::json_rpc())
         .and(json_rpc::method("getLatestKeyBlock"))
+        .and_then(|state: Arc<State>, res| async move {
+            wrap(res, "getLatestKeyBlock", state.get_latest_key_block()).await
+        })
+        .boxed()
+}
+
+pub fn get_config_params(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("getConfigParams");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("getConfigParams"))
+        .and(json_rpc::params())
+        .and_then(|state: Arc<State>, res, req: GetConfigParams| async move {
+            wrap(
+                res,
+                "getConfigParams",
+                state.get_config_params(req.source, req.params),
+            )
+            .await
+        })
+        .boxed()
+}
+
+pub fn get_shards(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("getShards");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("getShards"))
+        .and_then(|state: Arc<State>, res| async move {
+            wrap(res, "getShards", state.get_shards()).await
+        })
+        .boxed()
+}
+
+pub fn get_block_transactions(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("getBlockTransactions");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("getBlockTransactions"))
+        .and(json_rpc::params())
+        .and_then(
+            |state: Arc<State>, res, req: GetBlockTransactions| async move {
+                wrap(
+                    res,
+                    "getBlockTransactions",
+                    state.get_block_transactions(req.id, req.after, req.count, req.with_data),
+                )
+                .await
+            },
+        )
+        .boxed()
+}
+
+pub fn lookup_block_by_seqno(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("lookupBlockBySeqno");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("lookupBlockBySeqno"))
+        .and(json_rpc::params())
+        .and_then(
+            |state: Arc<State>, res, req: LookupBlockBySeqno| async move {
+                wrap(
+                    res,
+                    "lookupBlockBySeqno",
+                    state.lookup_block(
+                        req.workchain,
+                        req.shard,
+                        BlockLookup::Seqno(req.seqno),
+                        req.with_block,
+                    ),
+                )
+                .await
+            },
+        )
+        .boxed()
+}
+
+pub fn lookup_block_by_lt(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("lookupBlockByLt");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("lookupBlockByLt"))
+        .and(json_rpc::params())
+        .and_then(|state: Arc<State>, res, req: LookupBlockByLt| async move {
+            wrap(
+                res,
+                "lookupBlockByLt",
+                state.lookup_block(
+                    req.workchain,
+                    req.shard,
+                    BlockLookup::Lt(req.lt),
+                    req.with_block,
+                ),
+            )
+            .await
+        })
+        .boxed()
+}
+
+pub fn lookup_block_by_utime(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("lookupBlockByUtime");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || state.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("lookupBlockByUtime"))
+        .and(json_rpc::params())
         .and_then(
-            |state: Arc<State>, res| async move { wrap(res, state.get_latest_key_block().await) },
+            |state: Arc<State>, res, req: LookupBlockByUtime| async move {
+                wrap(
+                    res,
+                    "lookupBlockByUtime",
+                    state.lookup_block(
+                        req.workchain,
+                        req.shard,
+                        BlockLookup::Utime(req.utime),
+                        req.with_block,
+                    ),
+                )
+                .await
+            },
         )
         .boxed()
 }
 
+/// Admin method, see `auth::ADMIN_METHODS`
+pub fn reload_config(reloader: Arc<ConfigReloader>) -> BoxedFilter<(impl warp::Reply,)> {
+    log::debug!("reloadConfig");
+    warp::path(RPC_API_PATH)
+        .and(warp::path::end())
+        .map(move || reloader.clone())
+        .and(json_rpc::json_rpc())
+        .and(json_rpc::method("reloadConfig"))
+        .and_then(|reloader: Arc<ConfigReloader>, res| async move {
+            wrap(res, "reloadConfig", async move {
+                reloader
+                    .reload()
+                    .await
+                    .map(|_| true)
+                    .map_err(|e| QueryError::InvalidConfig(e.to_string()))
+            })
+            .await
+        })
+        .boxed()
+}
+
+pub fn prometheus_metrics(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
+    warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || state.clone())
+        .and_then(|state: Arc<State>| async move {
+            state.update_metrics().await;
+            Ok::<_, Rejection>(metrics::gather())
+        })
+        .boxed()
+}
+
 pub fn ws_stream(state: Arc<State>) -> BoxedFilter<(impl warp::Reply,)> {
-    warp::path::path("stream")
+    warp::path::path(STREAM_API_PATH)
         .and(warp::path::end())
         .map(move || state.clone())
         .and(warp::ws())
-        .map(|state: Arc<State>, ws: warp::ws::Ws| {
-            ws.on_upgrade(move |websocket| async move { state.handle_websocket(websocket).await })
-        })
+        .and(warp::ext::optional::<SubscriptionsLimit>())
+        .map(
+            |state: Arc<State>, ws: warp::ws::Ws, limit: Option<SubscriptionsLimit>| {
+                ws.on_upgrade(move |websocket| async move {
+                    state
+                        .handle_websocket(websocket, limit.map(|SubscriptionsLimit(limit)| limit))
+                        .await
+                })
+            },
+        )
         .boxed()
 }
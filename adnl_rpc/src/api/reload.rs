@@ -0,0 +1,312 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+use crate::ton::State;
+
+/// Reads the new config on reload requests
+pub type ConfigLoader = Box<dyn Fn() -> Result<Config> + Send + Sync>;
+
+/// Fields which are applied by rebuilding lite server pools
+const POOL_FIELDS: &[&str] = &[
+    "adnl_config",
+    "max_connection_count",
+    "min_idle_connection_count",
+];
+
+/// Fields which are applied without a restart
+const RELOADABLE_FIELDS: &[&str] = &[
+    "adnl_config",
+    "max_connection_count",
+    "min_idle_connection_count",
+    "max_unreliability",
+    "max_time_diff",
+    "logger_settings",
+];
+
+/// Fields which values are not printed in the diff
+const SECRET_FIELDS: &[&str] = &["api_keys"];
+
+pub struct ConfigReloader {
+    state: Arc<State>,
+    logger: log4rs::Handle,
+    load_config: ConfigLoader,
+    current: Mutex<Config>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        state: Arc<State>,
+        logger: log4rs::Handle,
+        load_config: ConfigLoader,
+        current: Config,
+    ) -> Result<Self> {
+        Ok(Self {
+            state,
+            logger,
+            load_config,
+            current: Mutex::new(resolve_lite_servers(current)?),
+        })
+    }
+
+    /// Reloads the config on SIGHUP until the server is shut down
+    pub fn start_signal_listener(self: &Arc<Self>) {
+        let reloader = self.clone();
+
+        tokio::spawn(async move {
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    log::error!("Failed to listen for SIGHUP: {}", e);
+                    return;
+                }
+            };
+
+            loop {
+                tokio::select! {
+                    _ = hangup.recv() => {}
+                    _ = reloader.state.wait_for_shutdown() => break,
+                }
+
+                log::info!("Reloading config");
+                // Errors are logged by `reload`
+                let _ = reloader.reload().await;
+            }
+        });
+    }
+
+    /// Reads the config and applies the changed fields. The current config
+    /// is left unchanged if the new one can't be applied
+    pub async fn reload(&self) -> Result<()> {
+        let mut current = self.current.lock().await;
+        let config = match (self.load_config)().and_then(resolve_lite_servers) {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Failed to load new config: {:?}", e);
+                return Err(e);
+            }
+        };
+
+        let changes = match diff(&current, &config) {
+            Ok(changes) => changes,
+            Err(e) => {
+                log::error!("Failed to compare configs: {:?}", e);
+                return Err(e);
+            }
+        };
+        if changes.is_empty() {
+            log::info!("Config is not changed");
+            return Ok(());
+        }
+
+        let summary = changes
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if let Err(e) = self.apply(&config, &changes).await {
+            log::error!("Rejected new config: {:?}\n{}", e, summary);
+            return Err(e);
+        }
+        log::info!("Applied new config:\n{}", summary);
+
+        for change in &changes {
+            if !RELOADABLE_FIELDS.contains(&change.field.as_str()) {
+                log::warn!("Changed `{}` requires a restart", change.field);
+            }
+        }
+
+        // Other fields keep their running values until restart
+        merge_reloadable_fields(&mut current, config);
+        Ok(())
+    }
+
+    async fn apply(&self, config: &Config, changes: &[ConfigChange]) -> Result<()> {
+        let changed = |fields: &[&str]| {
+            changes
+                .iter()
+                .any(|change| fields.contains(&change.field.as_str()))
+        };
+
+        // Logger config is validated before any changes are made
+        let logger_config = if changed(&["logger_settings"]) {
+            Some(config.logger_config()?)
+        } else {
+            None
+        };
+
+        self.state
+            .apply_config(config, changed(POOL_FIELDS))
+            .await?;
+
+        if let Some(logger_config) = logger_config {
+            self.logger.set_config(logger_config);
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the global config path with the lite servers from it, so that
+/// changes of the global config file are detected by `diff`
+fn resolve_lite_servers(mut config: Config) -> Result<Config> {
+    config.adnl_config.lite_servers = config.adnl_config.lite_servers()?;
+    config.adnl_config.global_config_path = None;
+    Ok(config)
+}
+
+/// Copies the fields from `RELOADABLE_FIELDS` into the current config
+fn merge_reloadable_fields(current: &mut Config, config: Config) {
+    current.adnl_config = config.adnl_config;
+    current.max_connection_count = config.max_connection_count;
+    current.min_idle_connection_count = config.min_idle_connection_count;
+    current.max_unreliability = config.max_unreliability;
+    current.max_time_diff = config.max_time_diff;
+    current.logger_settings = config.logger_settings;
+}
+
+struct ConfigChange {
+    field: String,
+    old: serde_yaml::Value,
+    new: serde_yaml::Value,
+}
+
+impl std::fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if SECRET_FIELDS.contains(&self.field.as_str()) {
+            return write!(f, "  {}: <hidden>", self.field);
+        }
+
+        let format = |value: &serde_yaml::Value| serde_json::to_string(value).unwrap_or_default();
+        write!(
+            f,
+            "  {}: {} -> {}",
+            self.field,
+            format(&self.old),
+            format(&self.new)
+        )
+    }
+}
+
+/// Compares top level config fields
+fn diff(old: &Config, new: &Config) -> Result<Vec<ConfigChange>> {
+    let old = serde_yaml::to_value(old)?;
+    let new = serde_yaml::to_value(new)?;
+
+    let (old, new) = match (old, new) {
+        (serde_yaml::Value::Mapping(old), serde_yaml::Value::Mapping(new)) => (old, new),
+        _ => return Ok(Vec::new()),
+    };
+
+    Ok(new
+        .iter()
+        .filter_map(|(field, value)| {
+            let old_value = old.get(field).cloned().unwrap_or_default();
+            if &old_value == value {
+                return None;
+            }
+            Some(ConfigChange {
+                field: field.as_str().unwrap_or_default().to_owned(),
+                old: old_value,
+                new: value.clone(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    use super::*;
+    use crate::config::{ApiKeyConfig, LiteServerConfig};
+
+    const SERVER_KEY: &str = "uNRRL+6enQjuiZ/s6Z+vO7yxUUR7uxdfzIy+RxkECrc=";
+
+    fn changed_fields(old: &Config, new: &Config) -> Vec<String> {
+        diff(old, new)
+            .unwrap()
+            .into_iter()
+            .map(|change| change.field)
+            .collect()
+    }
+
+    fn write_global_config(path: &std::path::Path, port: u16) {
+        let config = serde_json::json!({
+            "liteservers": [{
+                "ip": u32::from(Ipv4Addr::new(127, 0, 0, 1)),
+                "port": port,
+                "id": { "key": SERVER_KEY },
+            }],
+        });
+        std::fs::write(path, config.to_string()).unwrap();
+    }
+
+    #[test]
+    fn unchanged_config() {
+        assert!(changed_fields(&Config::default(), &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn changed_fields_are_listed() {
+        let old = Config::default();
+        let mut new = Config::default();
+        new.max_time_diff += 1;
+        new.shutdown_timeout *= 2;
+
+        let mut fields = changed_fields(&old, &new);
+        fields.sort();
+        assert_eq!(fields, ["max_time_diff", "shutdown_timeout"]);
+    }
+
+    #[test]
+    fn secret_fields_are_hidden() {
+        let old = Config::default();
+        let mut new = Config::default();
+        new.api_keys.push(ApiKeyConfig {
+            name: "test".to_owned(),
+            key: "secret".to_owned(),
+            requests_per_second: 1,
+            max_concurrent_requests: 1,
+            max_subscriptions: 1,
+            allowed_methods: Vec::new(),
+            admin: false,
+        });
+
+        let changes = diff(&old, &new).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].to_string(), "  api_keys: <hidden>");
+    }
+
+    #[test]
+    fn global_config_changes_are_detected() {
+        let path = std::env::temp_dir().join(format!(
+            "adnl-rpc-global-config-{}.json",
+            uuid::Uuid::new_v4()
+        ));
+
+        let mut config = Config::default();
+        config.adnl_config.lite_servers = vec![LiteServerConfig {
+            server_address: SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 3000),
+            server_key: SERVER_KEY.to_owned(),
+        }];
+        config.adnl_config.global_config_path = Some(path.clone());
+
+        write_global_config(&path, 3001);
+        let old = resolve_lite_servers(config.clone()).unwrap();
+        assert_eq!(old.adnl_config.lite_servers.len(), 2);
+        assert!(old.adnl_config.global_config_path.is_none());
+
+        let same = resolve_lite_servers(config.clone()).unwrap();
+        assert!(changed_fields(&old, &same).is_empty());
+
+        write_global_config(&path, 3002);
+        let new = resolve_lite_servers(config).unwrap();
+        assert_eq!(changed_fields(&old, &new), ["adnl_config"]);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,265 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use futures::{future, stream, StreamExt};
+use http::{Method, Request, Response};
+use hyper::body::HttpBody;
+use hyper::service::Service;
+use hyper::Body;
+use tokio::sync::OwnedSemaphorePermit;
+use warp::http::StatusCode;
+
+use super::auth::{is_admin_method, ApiKey, ApiKeys, AuthError, SubscriptionsLimit};
+use super::{new_error_response, RPC_API_PATH, STREAM_API_PATH};
+
+/// Max size of the JSON-RPC request body in bytes
+const MAX_BODY_SIZE: usize = 4 << 20;
+/// Max number of calls in one batch request
+const MAX_BATCH_SIZE: usize = 100;
+/// Max number of calls from one batch which are executed concurrently
+const MAX_CONCURRENT_BATCH_CALLS: usize = 16;
+
+const INVALID_REQUEST_CODE: i64 = -32600;
+
+/// Handles the request with the JSON-RPC service, splitting batch requests
+/// into separate calls which are executed concurrently. Each call is checked
+/// against the quotas of the request API key
+pub async fn handle_request<S>(
+    service: S,
+    api_keys: Arc<ApiKeys>,
+    mut request: Request<Body>,
+) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible> + Clone,
+{
+    let path = request.uri().path().trim_matches('/');
+    let is_rpc = request.method() == Method::POST && path == RPC_API_PATH;
+    let is_stream = path == STREAM_API_PATH;
+    if !is_rpc && !is_stream {
+        return call(service, request).await;
+    }
+
+    let api_key = match api_keys.authenticate(&request) {
+        Ok(api_key) => api_key,
+        Err(error) => return Ok(new_error_response(error.into())),
+    };
+
+    if is_stream {
+        if let Some(api_key) = &api_key {
+            request
+                .extensions_mut()
+                .insert(SubscriptionsLimit(api_key.max_subscriptions()));
+        }
+        return call(service, request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let body = match read_body(&parts, body).await {
+        Ok(body) => body,
+        Err(error) => return Ok(new_error_response(error)),
+    };
+
+    let batch = match body.iter().find(|c| !c.is_ascii_whitespace()) {
+        Some(b'[') => match serde_json::from_slice::<Vec<serde_json::Value>>(&body) {
+            Ok(batch) => batch,
+            Err(_) => return Ok(new_error_response(warp_json_rpc::Error::PARSE_ERROR)),
+        },
+        _ => {
+            let item = serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default();
+            let _permit = match check_quotas(api_key.as_deref(), &item) {
+                Ok(permit) => permit,
+                Err(error) => return Ok(json_response(&error)),
+            };
+            return call(service, Request::from_parts(parts, Body::from(body))).await;
+        }
+    };
+
+    if batch.is_empty() {
+        return Ok(new_error_response(warp_json_rpc::Error::INVALID_REQUEST));
+    }
+    if batch.len() > MAX_BATCH_SIZE {
+        return Ok(new_error_response(warp_json_rpc::Error::custom(
+            INVALID_REQUEST_CODE,
+            format!("Batch is too large, max size is {}", MAX_BATCH_SIZE),
+        )));
+    }
+    if let Some(api_key) = &api_key {
+        if let Err(error) = api_key.check_batch() {
+            return Ok(new_error_response(error.into()));
+        }
+    }
+
+    let calls = batch.into_iter().map(|item| {
+        let service = service.clone();
+        let api_key = api_key.clone();
+
+        let mut request = Request::new(Body::from(item.to_string()));
+        *request.method_mut() = parts.method.clone();
+        *request.uri_mut() = parts.uri.clone();
+        *request.headers_mut() = parts.headers.clone();
+        // Responses are combined into one array, so they must not be compressed separately
+        request.headers_mut().remove(http::header::ACCEPT_ENCODING);
+        // Length of the whole batch doesn't match the body of the single call
+        request.headers_mut().remove(http::header::CONTENT_LENGTH);
+
+        async move {
+            if !item.is_object() {
+                return Some(invalid_request());
+            }
+            // Notifications are executed, but nothing is returned for them
+            let is_notification = item.get("id").is_none();
+
+            let _permit = match check_batch_call_quotas(api_key.as_deref(), &item).await {
+                Ok(permit) => permit,
+                Err(_) if is_notification => return None,
+                Err(error) => return Some(error),
+            };
+
+            let response = match call(service, request).await {
+                Ok(response) => hyper::body::to_bytes(response.into_body()).await.ok(),
+                Err(_) => None,
+            };
+
+            if is_notification {
+                return None;
+            }
+
+            // Failed calls still get a response, so that clients can match all ids
+            let response = response
+                .and_then(|body| serde_json::from_slice::<serde_json::Value>(&body).ok())
+                .unwrap_or_else(|| error_item(&item, warp_json_rpc::Error::INTERNAL_ERROR));
+            Some(response)
+        }
+    });
+
+    // Responses are collected in the order of the calls
+    let responses = stream::iter(calls)
+        .buffered(MAX_CONCURRENT_BATCH_CALLS)
+        .filter_map(future::ready)
+        .collect::<Vec<_>>()
+        .await;
+
+    if responses.is_empty() {
+        return Ok(Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    Ok(json_response(&responses))
+}
+
+/// Checks the quotas of the API key for the JSON-RPC call. Returns an error
+/// response with the call id if the call is rejected
+fn check_quotas(
+    api_key: Option<&ApiKey>,
+    item: &serde_json::Value,
+) -> Result<Option<OwnedSemaphorePermit>, serde_json::Value> {
+    let method = item.get("method").and_then(|method| method.as_str());
+    match authorized_key(api_key, method) {
+        Ok(Some(api_key)) => api_key.acquire(method).map(Some),
+        Ok(None) => Ok(None),
+        Err(error) => Err(error),
+    }
+    .map_err(|error| error_item(item, error))
+}
+
+/// Same as `check_quotas`, but waits for a concurrency slot of the API key
+async fn check_batch_call_quotas(
+    api_key: Option<&ApiKey>,
+    item: &serde_json::Value,
+) -> Result<Option<OwnedSemaphorePermit>, serde_json::Value> {
+    let method = item.get("method").and_then(|method| method.as_str());
+    match authorized_key(api_key, method) {
+        Ok(Some(api_key)) => api_key.acquire_batch_call(method).await.map(Some),
+        Ok(None) => Ok(None),
+        Err(error) => Err(error),
+    }
+    .map_err(|error| error_item(item, error))
+}
+
+/// Returns `None` if authentication is disabled and the method is allowed without it
+fn authorized_key<'a>(
+    api_key: Option<&'a ApiKey>,
+    method: Option<&str>,
+) -> Result<Option<&'a ApiKey>, AuthError> {
+    match api_key {
+        Some(api_key) => Ok(Some(api_key)),
+        // Admin methods are disabled without authentication
+        None if matches!(method, Some(method) if is_admin_method(method)) => {
+            Err(AuthError::MethodNotAllowed)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Reads the request body, rejecting it as soon as it exceeds the size limit
+async fn read_body(
+    parts: &http::request::Parts,
+    mut body: Body,
+) -> Result<Vec<u8>, warp_json_rpc::Error> {
+    let content_length = parts
+        .headers
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if matches!(content_length, Some(length) if length > MAX_BODY_SIZE) {
+        return Err(body_too_large());
+    }
+
+    let mut data = Vec::with_capacity(content_length.unwrap_or_default());
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| warp_json_rpc::Error::PARSE_ERROR)?;
+        if data.len() + chunk.len() > MAX_BODY_SIZE {
+            return Err(body_too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+fn body_too_large() -> warp_json_rpc::Error {
+    warp_json_rpc::Error::custom(
+        INVALID_REQUEST_CODE,
+        format!(
+            "Request body is too large, max size is {} bytes",
+            MAX_BODY_SIZE
+        ),
+    )
+}
+
+fn error_item<E: Into<warp_json_rpc::Error>>(
+    item: &serde_json::Value,
+    error: E,
+) -> serde_json::Value {
+    let error: warp_json_rpc::Error = error.into();
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": item.get("id").cloned().unwrap_or_default(),
+        "error": error,
+    })
+}
+
+fn json_response<T: serde::Serialize>(body: &T) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(body).unwrap()))
+        .unwrap()
+}
+
+async fn call<S>(mut service: S, request: Request<Body>) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    future::poll_fn(|cx| service.poll_ready(cx)).await?;
+    service.call(request).await
+}
+
+fn invalid_request() -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": warp_json_rpc::Error::INVALID_REQUEST,
+    })
+}
@@ -0,0 +1,169 @@
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use futures::channel::mpsc;
+use hyper::server::accept::Accept;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::{
+    AllowAnyAuthenticatedClient, NoClientAuth, RootCertStore, ServerConfig,
+};
+use tokio_rustls::server::TlsStream;
+
+use crate::config::TlsConfig;
+
+const CERTIFICATES_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Delay after a failed accept, errors like running out of file descriptors
+/// usually persist for a while
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+/// TLS acceptor which reloads certificates when their files are changed
+pub struct TlsAcceptor {
+    config: TlsConfig,
+    server_config: parking_lot::RwLock<Arc<ServerConfig>>,
+}
+
+impl TlsAcceptor {
+    pub fn new(config: TlsConfig) -> Result<Self> {
+        let server_config = load_server_config(&config)?;
+        Ok(Self {
+            config,
+            server_config: parking_lot::RwLock::new(Arc::new(server_config)),
+        })
+    }
+
+    pub fn start_reloader(self: &Arc<Self>) {
+        let acceptor = Arc::downgrade(self);
+        let mut last_modified = self.last_modified();
+
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(CERTIFICATES_CHECK_INTERVAL).await;
+
+                let acceptor = match acceptor.upgrade() {
+                    Some(acceptor) => acceptor,
+                    None => break,
+                };
+
+                let modified = acceptor.last_modified();
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+
+                match load_server_config(&acceptor.config) {
+                    Ok(server_config) => {
+                        *acceptor.server_config.write() = Arc::new(server_config);
+                        log::info!("Reloaded TLS certificates");
+                    }
+                    Err(e) => log::error!("Failed to reload TLS certificates: {:?}", e),
+                }
+            }
+        });
+    }
+
+    fn acceptor(&self) -> tokio_rustls::TlsAcceptor {
+        tokio_rustls::TlsAcceptor::from(self.server_config.read().clone())
+    }
+
+    fn last_modified(&self) -> Vec<Option<SystemTime>> {
+        std::iter::once(&self.config.cert_path)
+            .chain(std::iter::once(&self.config.key_path))
+            .chain(self.config.client_ca_path.as_ref())
+            .map(|path| {
+                std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            })
+            .collect()
+    }
+}
+
+/// Accepts TCP connections and performs TLS handshakes in the background,
+/// so a slow client doesn't block other connections. The listener is closed
+/// when the shutdown future completes
+pub fn incoming<F>(
+    listener: TcpListener,
+    acceptor: Arc<TlsAcceptor>,
+    shutdown: F,
+) -> impl Accept<Conn = TlsStream<TcpStream>, Error = std::io::Error>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (tx, rx) = mpsc::unbounded();
+
+    tokio::spawn(async move {
+        tokio::pin!(shutdown);
+
+        while !tx.is_closed() {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = &mut shutdown => break,
+            };
+
+            let (stream, address) = match accepted {
+                Ok(connection) => connection,
+                Err(e) => {
+                    log::error!("Failed to accept connection: {}", e);
+                    tokio::select! {
+                        _ = tokio::time::sleep(ACCEPT_ERROR_DELAY) => continue,
+                        _ = &mut shutdown => break,
+                    }
+                }
+            };
+
+            let acceptor = acceptor.acceptor();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.unbounded_send(Ok(stream));
+                    }
+                    Ok(Err(e)) => log::debug!("TLS handshake with {} failed: {}", address, e),
+                    Err(_) => log::debug!("TLS handshake with {} timed out", address),
+                }
+            });
+        }
+    });
+
+    hyper::server::accept::from_stream(rx)
+}
+
+fn load_server_config(config: &TlsConfig) -> Result<ServerConfig> {
+    let client_verifier = match &config.client_ca_path {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            roots
+                .add_pem_file(&mut open(path)?)
+                .map_err(|_| anyhow::anyhow!("Invalid client CA certificates"))?;
+            AllowAnyAuthenticatedClient::new(roots)
+        }
+        None => NoClientAuth::new(),
+    };
+
+    let certs = pemfile::certs(&mut open(&config.cert_path)?)
+        .map_err(|_| anyhow::anyhow!("Invalid certificate chain"))?;
+
+    let key = pemfile::pkcs8_private_keys(&mut open(&config.key_path)?)
+        .ok()
+        .filter(|keys| !keys.is_empty())
+        .or_else(|| pemfile::rsa_private_keys(&mut open(&config.key_path).ok()?).ok())
+        .and_then(|keys| keys.into_iter().next())
+        .context("Invalid private key")?;
+
+    let mut server_config = ServerConfig::new(client_verifier);
+    server_config.set_single_cert(certs, key)?;
+    server_config.set_protocols(&[b"http/1.1".to_vec()]);
+    Ok(server_config)
+}
+
+fn open(path: &Path) -> Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(BufReader::new(file))
+}
@@ -1,17 +1,21 @@
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-pub use ton_config::AdnlConfig;
+pub use ton_config::{AdnlConfig, LiteServerConfig};
 
 mod ton_config;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub listen_address: SocketAddr,
 
+    /// Serve HTTPS and WSS instead of plain connections
+    pub tls: Option<TlsConfig>,
+
     #[serde(default = "default_logger_settings")]
     pub logger_settings: serde_yaml::Value,
 
@@ -30,12 +34,73 @@ pub struct Config {
 
     #[serde(with = "serde_time")]
     pub indexer_interval: Duration,
+
+    /// Max time to wait for the transaction of the sent message
+    #[serde(with = "serde_time", default = "default_message_wait_timeout")]
+    pub message_wait_timeout: Duration,
+
+    /// Max time to wait for in-flight requests and WebSocket connections on shutdown
+    #[serde(with = "serde_time", default = "default_shutdown_timeout")]
+    pub shutdown_timeout: Duration,
+
+    /// Max number of contract states cached for the recent masterchain blocks.
+    /// The cache is disabled if zero
+    #[serde(default = "default_contract_states_cache_size")]
+    pub contract_states_cache_size: usize,
+
+    /// API keys with their quotas. Requests are not authenticated if empty
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain
+    pub cert_path: PathBuf,
+
+    /// PEM file with the PKCS8 or RSA private key
+    pub key_path: PathBuf,
+
+    /// PEM file with CA certificates. Clients must present a certificate signed
+    /// by one of them if specified
+    pub client_ca_path: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKeyConfig {
+    /// Name used in logs and metrics instead of the key itself
+    pub name: String,
+
+    pub key: String,
+
+    pub requests_per_second: u32,
+
+    pub max_concurrent_requests: usize,
+
+    pub max_subscriptions: usize,
+
+    /// JSON-RPC methods allowed for this key. All methods are allowed if empty
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+
+    /// Allows admin methods like `reloadConfig`
+    #[serde(default)]
+    pub admin: bool,
+}
+
+impl Config {
+    /// Builds the logger config from `logger_settings`
+    pub fn logger_config(&self) -> anyhow::Result<log4rs::Config> {
+        let config = serde_yaml::from_value(self.logger_settings.clone())?;
+        Ok(log4rs::config::create_raw_config(config)?)
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             listen_address: "127.0.0.1:9000".parse().unwrap(),
+            tls: None,
             logger_settings: default_logger_settings(),
             adnl_config: AdnlConfig::default_mainnet_config(),
             max_unreliability: 30,
@@ -44,6 +109,10 @@ impl Default for Config {
             min_idle_connection_count: Some(5),
             last_block_cache_duration: Duration::from_secs(1),
             indexer_interval: Duration::from_secs(10),
+            message_wait_timeout: default_message_wait_timeout(),
+            shutdown_timeout: default_shutdown_timeout(),
+            contract_states_cache_size: default_contract_states_cache_size(),
+            api_keys: Vec::new(),
         }
     }
 }
@@ -69,6 +138,18 @@ fn default_logger_settings() -> serde_yaml::Value {
     serde_yaml::from_str(DEFAULT_LOG4RS_SETTINGS).unwrap()
 }
 
+fn default_message_wait_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_contract_states_cache_size() -> usize {
+    10000
+}
+
 pub mod serde_time {
     use super::*;
 
@@ -1,5 +1,5 @@
-use std::convert::TryFrom;
 use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use anyhow::Result;
@@ -8,40 +8,117 @@ use tiny_adnl::AdnlTcpClientConfig;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AdnlConfig {
+    #[serde(default)]
+    pub lite_servers: Vec<LiteServerConfig>,
+
+    /// Path to the global config JSON. Lite servers from it are added to `lite_servers`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global_config_path: Option<PathBuf>,
+
+    pub socket_timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LiteServerConfig {
     pub server_address: SocketAddrV4,
     pub server_key: String,
-    pub socket_timeout_ms: u64,
 }
 
-impl TryFrom<AdnlConfig> for AdnlTcpClientConfig {
-    type Error = anyhow::Error;
+impl AdnlConfig {
+    pub fn from_global_config<P>(path: P) -> Result<AdnlConfig>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(AdnlConfig {
+            lite_servers: read_global_config(path)?,
+            global_config_path: None,
+            socket_timeout_ms: 20000,
+        })
+    }
 
-    fn try_from(c: AdnlConfig) -> Result<Self, Self::Error> {
-        let server_key = base64::decode(&c.server_key)?;
+    pub fn lite_servers(&self) -> Result<Vec<LiteServerConfig>> {
+        let mut lite_servers = self.lite_servers.clone();
+        if let Some(path) = &self.global_config_path {
+            lite_servers.extend(read_global_config(path)?);
+        }
 
-        Ok(AdnlTcpClientConfig {
-            server_address: c.server_address,
-            server_key: ed25519_dalek::PublicKey::from_bytes(&server_key)?,
-            socket_read_timeout: Duration::from_millis(c.socket_timeout_ms),
-            socket_send_timeout: Duration::from_millis(c.socket_timeout_ms),
-        })
+        if lite_servers.is_empty() {
+            return Err(anyhow::anyhow!("No lite servers specified"));
+        }
+
+        Ok(lite_servers)
+    }
+
+    pub fn tcp_client_configs(&self) -> Result<Vec<AdnlTcpClientConfig>> {
+        self.lite_servers()?
+            .iter()
+            .map(|server| {
+                let server_key = base64::decode(&server.server_key)?;
+
+                Ok(AdnlTcpClientConfig {
+                    server_address: server.server_address,
+                    server_key: ed25519_dalek::PublicKey::from_bytes(&server_key)?,
+                    socket_read_timeout: Duration::from_millis(self.socket_timeout_ms),
+                    socket_send_timeout: Duration::from_millis(self.socket_timeout_ms),
+                })
+            })
+            .collect()
     }
-}
 
-impl AdnlConfig {
     pub fn default_mainnet_config() -> AdnlConfig {
         AdnlConfig {
-            server_address: SocketAddrV4::new(Ipv4Addr::new(54, 158, 97, 195), 3031),
-            server_key: "uNRRL+6enQjuiZ/s6Z+vO7yxUUR7uxdfzIy+RxkECrc=".to_owned(),
+            lite_servers: vec![LiteServerConfig {
+                server_address: SocketAddrV4::new(Ipv4Addr::new(54, 158, 97, 195), 3031),
+                server_key: "uNRRL+6enQjuiZ/s6Z+vO7yxUUR7uxdfzIy+RxkECrc=".to_owned(),
+            }],
+            global_config_path: None,
             socket_timeout_ms: 20000,
         }
     }
 
     pub fn default_testnet_config() -> AdnlConfig {
         AdnlConfig {
-            server_address: SocketAddrV4::new(Ipv4Addr::new(54, 158, 97, 195), 3032),
-            server_key: "uNRRL+6enQjuiZ/s6Z+vO7yxUUR7uxdfzIy+RxkECrc=".to_owned(),
+            lite_servers: vec![LiteServerConfig {
+                server_address: SocketAddrV4::new(Ipv4Addr::new(54, 158, 97, 195), 3032),
+                server_key: "uNRRL+6enQjuiZ/s6Z+vO7yxUUR7uxdfzIy+RxkECrc=".to_owned(),
+            }],
+            global_config_path: None,
             socket_timeout_ms: 20000,
         }
     }
 }
+
+/// Reads lite servers from the standard TON/Everscale global config
+fn read_global_config<P>(path: P) -> Result<Vec<LiteServerConfig>>
+where
+    P: AsRef<Path>,
+{
+    #[derive(Deserialize)]
+    struct GlobalConfig {
+        liteservers: Vec<GlobalConfigLiteServer>,
+    }
+
+    #[derive(Deserialize)]
+    struct GlobalConfigLiteServer {
+        ip: i64,
+        port: u16,
+        id: GlobalConfigKey,
+    }
+
+    #[derive(Deserialize)]
+    struct GlobalConfigKey {
+        key: String,
+    }
+
+    let file = std::fs::File::open(path.as_ref())?;
+    let config: GlobalConfig = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+    Ok(config
+        .liteservers
+        .into_iter()
+        .map(|server| LiteServerConfig {
+            server_address: SocketAddrV4::new(Ipv4Addr::from(server.ip as u32), server.port),
+            server_key: server.id.key,
+        })
+        .collect())
+}
@@ -1,6 +1,7 @@
 mod api;
 mod config;
+mod metrics;
 mod ton;
 
-pub use self::api::serve;
-pub use self::config::Config;
+pub use self::api::{serve, ConfigLoader};
+pub use self::config::{AdnlConfig, Config};
@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use anyhow::Result;
 use clap::{Clap, IntoApp};
 
-use adnl_rpc::Config;
+use adnl_rpc::{AdnlConfig, Config};
 
 #[derive(Clone, Debug, Clap)]
 pub struct Arguments {
@@ -14,6 +14,10 @@ pub struct Arguments {
     /// Generate default config
     #[clap(long)]
     pub gen_config: Option<PathBuf>,
+
+    /// Path to the global config JSON with lite servers for the generated config
+    #[clap(long, requires = "gen-config")]
+    pub global_config: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -21,12 +25,17 @@ async fn main() -> Result<()> {
     let args: Arguments = Arguments::parse();
 
     match (args.config, args.gen_config) {
-        (_, Some(new_config_path)) => generate_config(new_config_path)?,
-        (Some(config), None) => {
-            let config = read_config(config)?;
-            init_logger(&config.logger_settings)?;
+        (_, Some(new_config_path)) => generate_config(new_config_path, args.global_config)?,
+        (Some(config_path), None) => {
+            let config = read_config(config_path.clone())?;
+            let logger = log4rs::init_config(config.logger_config()?)?;
 
-            adnl_rpc::serve(config).await?
+            adnl_rpc::serve(
+                config,
+                logger,
+                Box::new(move || read_config(config_path.clone())),
+            )
+            .await?
         }
         _ => Arguments::into_app().print_help()?,
     }
@@ -34,14 +43,18 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-pub fn generate_config<T>(path: T) -> Result<()>
+pub fn generate_config<T>(path: T, global_config: Option<PathBuf>) -> Result<()>
 where
     T: AsRef<std::path::Path>,
 {
     use std::io::Write;
 
+    let mut config = Config::default();
+    if let Some(global_config) = global_config {
+        config.adnl_config = AdnlConfig::from_global_config(global_config)?;
+    }
+
     let mut file = std::fs::File::create(path)?;
-    let config = Config::default();
     file.write_all(serde_yaml::to_string(&config)?.as_bytes())?;
     Ok(())
 }
@@ -54,9 +67,3 @@ pub fn read_config(path: PathBuf) -> Result<Config> {
     let config: Config = config.try_into()?;
     Ok(config)
 }
-
-fn init_logger(config: &serde_yaml::Value) -> Result<()> {
-    let config = serde_yaml::from_value(config.clone())?;
-    log4rs::config::init_raw_config(config)?;
-    Ok(())
-}
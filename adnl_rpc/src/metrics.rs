@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, TextEncoder,
+};
+
+use crate::ton::QueryError;
+
+pub static REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "adnl_rpc_requests_total",
+        "Number of handled JSON-RPC requests",
+        &["method"]
+    )
+    .unwrap()
+});
+
+pub static REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "adnl_rpc_request_duration_seconds",
+        "JSON-RPC request handling time",
+        &["method"]
+    )
+    .unwrap()
+});
+
+pub static QUERY_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "adnl_rpc_query_errors_total",
+        "Number of failed requests by error variant and lite server error code",
+        &["error", "code"]
+    )
+    .unwrap()
+});
+
+pub static NOT_READY_RETRIES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "adnl_rpc_not_ready_retries_total",
+        "Number of lite server queries retried after the NOT_READY error"
+    )
+    .unwrap()
+});
+
+pub static POOL_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "adnl_rpc_pool_connections",
+        "Number of connections to the lite server",
+        &["server"]
+    )
+    .unwrap()
+});
+
+pub static POOL_IDLE_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "adnl_rpc_pool_idle_connections",
+        "Number of idle connections to the lite server",
+        &["server"]
+    )
+    .unwrap()
+});
+
+pub static UNRELIABILITY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "adnl_rpc_server_unreliability",
+        "Lite server unreliability points",
+        &["server"]
+    )
+    .unwrap()
+});
+
+pub static TIME_DIFF: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "adnl_rpc_time_diff_seconds",
+        "Time between now and the latest masterchain block"
+    )
+    .unwrap()
+});
+
+pub static LAST_MC_SEQNO: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "adnl_rpc_last_mc_seqno",
+        "Seqno of the latest known masterchain block"
+    )
+    .unwrap()
+});
+
+pub static WS_SUBSCRIPTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "adnl_rpc_ws_subscriptions",
+        "Number of active WebSocket subscriptions",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+pub static API_KEY_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "adnl_rpc_api_key_requests_total",
+        "Number of JSON-RPC requests made with the API key",
+        &["key"]
+    )
+    .unwrap()
+});
+
+pub static API_KEY_REJECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "adnl_rpc_api_key_rejections_total",
+        "Number of requests rejected by the API key quotas",
+        &["key", "reason"]
+    )
+    .unwrap()
+});
+
+pub fn record_request(method: &str, duration: Duration, error: Option<&QueryError>) {
+    REQUESTS.with_label_values(&[method]).inc();
+    REQUEST_DURATION
+        .with_label_values(&[method])
+        .observe(duration.as_secs_f64());
+
+    if let Some(error) = error {
+        let code = match error {
+            QueryError::LiteServer(error) => error.code().to_string(),
+            _ => String::new(),
+        };
+        QUERY_ERRORS
+            .with_label_values(&[error_name(error), &code])
+            .inc();
+    }
+}
+
+pub fn record_api_key_request(key: &str, rejection: Option<&str>) {
+    API_KEY_REQUESTS.with_label_values(&[key]).inc();
+    if let Some(reason) = rejection {
+        API_KEY_REJECTIONS.with_label_values(&[key, reason]).inc();
+    }
+}
+
+/// Encodes all registered metrics in the Prometheus text format
+pub fn gather() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+fn error_name(error: &QueryError) -> &'static str {
+    match error {
+        QueryError::ConnectionError => "connection_error",
+        QueryError::FailedToSerialize => "failed_to_serialize",
+        QueryError::LiteServer(_) => "lite_server",
+        QueryError::InvalidAccountStateProof => "invalid_account_state_proof",
+        QueryError::AccountStateProofMismatch => "account_state_proof_mismatch",
+        QueryError::InvalidConfigProof => "invalid_config_proof",
+        QueryError::ConfigProofMismatch => "config_proof_mismatch",
+        QueryError::InvalidBlock => "invalid_block",
+        QueryError::InvalidStack => "invalid_stack",
+        QueryError::InvalidAbi(_) => "invalid_abi",
+        QueryError::AccountNotFound => "account_not_found",
+        QueryError::ExecutionFailed(_) => "execution_failed",
+        QueryError::MessageExpired => "message_expired",
+        QueryError::InvalidMessage => "invalid_message",
+        QueryError::TransactionNotFound => "transaction_not_found",
+        QueryError::InvalidConfig(_) => "invalid_config",
+        QueryError::Unknown => "unknown",
+        QueryError::NotReady => "not_ready",
+    }
+}
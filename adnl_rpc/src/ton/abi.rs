@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use ton_abi::token::{Detokenizer, Tokenizer};
+use ton_block::MsgAddressInt;
+use ton_types::{Cell, SliceData};
+
+use super::errors::*;
+
+pub fn load_function(abi: &str, name: &str) -> QueryResult<ton_abi::Function> {
+    let contract = ton_abi::Contract::load(std::io::Cursor::new(abi))
+        .map_err(|e| QueryError::InvalidAbi(e.to_string()))?;
+    contract
+        .function(name)
+        .cloned()
+        .map_err(|e| QueryError::InvalidAbi(e.to_string()))
+}
+
+/// Builds an unsigned external message which calls the function of the contract
+pub fn encode_external_call(
+    function: &ton_abi::Function,
+    address: MsgAddressInt,
+    mut input: serde_json::Value,
+) -> QueryResult<ton_block::Message> {
+    if input.is_null() {
+        input = serde_json::Value::Object(Default::default());
+    }
+
+    // Responsible functions require `answerId`, but it is not used for external calls
+    if matches!(function.inputs.first(), Some(param) if param.name == ANSWER_ID) {
+        if let Some(input) = input.as_object_mut() {
+            input
+                .entry(ANSWER_ID)
+                .or_insert_with(|| function.get_output_id().into());
+        }
+    }
+
+    let tokens = Tokenizer::tokenize_all_params(&function.inputs, &input)
+        .map_err(|e| QueryError::InvalidAbi(e.to_string()))?;
+    // Header values like `time` and `expire` are filled with defaults
+    let body = function
+        .encode_input(&HashMap::new(), &tokens, false, None)
+        .map_err(|e| QueryError::InvalidAbi(e.to_string()))?;
+
+    let mut message =
+        ton_block::Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+            dst: address,
+            ..Default::default()
+        });
+    message.set_body(SliceData::from(Cell::from(body)));
+
+    Ok(message)
+}
+
+/// Reads the `expire` header of the external inbound message body.
+/// Returns `None` if the ABI has no such header
+pub fn read_expire(abi: &str, message: &ton_block::Message) -> QueryResult<Option<u32>> {
+    let contract = ton_abi::Contract::load(std::io::Cursor::new(abi))
+        .map_err(|e| QueryError::InvalidAbi(e.to_string()))?;
+    let body = message.body().ok_or(QueryError::InvalidMessage)?;
+
+    let (header, _, _) =
+        ton_abi::Function::decode_header(contract.version(), body, contract.header(), false)
+            .map_err(|e| QueryError::InvalidAbi(e.to_string()))?;
+
+    Ok(header.into_iter().find_map(|token| match token.value {
+        ton_abi::TokenValue::Expire(expire) => Some(expire),
+        _ => None,
+    }))
+}
+
+/// Finds the function answer among the external out messages and decodes it
+pub fn decode_output(
+    function: &ton_abi::Function,
+    messages: &[ton_block::Message],
+) -> QueryResult<Option<serde_json::Value>> {
+    let tokens = messages
+        .iter()
+        .filter(|message| matches!(message.header(), ton_block::CommonMsgInfo::ExtOutMsgInfo(_)))
+        .find_map(|message| {
+            message
+                .body()
+                .and_then(|body| function.decode_output(body, false).ok())
+        });
+
+    match tokens {
+        Some(tokens) => {
+            let output = Detokenizer::detokenize(&tokens)
+                .map_err(|e| QueryError::InvalidAbi(e.to_string()))?;
+            serde_json::from_str(&output)
+                .map(Some)
+                .map_err(|e| QueryError::InvalidAbi(e.to_string()))
+        }
+        None => Ok(None),
+    }
+}
+
+const ANSWER_ID: &str = "answerId";
@@ -1,13 +1,168 @@
+use std::net::SocketAddrV4;
+use std::ops::{Deref, DerefMut};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Error;
 use async_trait::async_trait;
-use bb8::PooledConnection;
-use std::ops::DerefMut;
+use bb8::{Pool, PooledConnection};
 use tiny_adnl::{AdnlTcpClient, AdnlTcpClientConfig};
 
+use super::errors::*;
+use crate::config::Config;
+
+pub struct AdnlPool {
+    servers: Vec<Arc<LiteServer>>,
+    next_server: AtomicUsize,
+    max_unreliability: AtomicUsize,
+}
+
+impl AdnlPool {
+    /// Creates pools without waiting for connections, so that unreachable
+    /// lite servers are only marked as unreliable
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let mut servers = Vec::with_capacity(config.adnl_config.lite_servers.len());
+        for client_config in config.adnl_config.tcp_client_configs()? {
+            let address = client_config.server_address;
+            let unreliability = Arc::new(AtomicUsize::new(0));
+
+            let pool = Pool::builder()
+                .max_size(config.max_connection_count)
+                .min_idle(config.min_idle_connection_count)
+                .max_lifetime(None)
+                .build_unchecked(AdnlManageConnection::new(
+                    client_config,
+                    unreliability.clone(),
+                ));
+
+            servers.push(Arc::new(LiteServer {
+                address,
+                pool,
+                unreliability,
+            }));
+        }
+
+        Ok(Self {
+            servers,
+            next_server: AtomicUsize::new(0),
+            max_unreliability: AtomicUsize::new(config.max_unreliability),
+        })
+    }
+
+    /// Pool without lite servers, all queries fail with the connection error
+    pub fn empty() -> Self {
+        Self {
+            servers: Vec::new(),
+            next_server: AtomicUsize::new(0),
+            max_unreliability: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn servers(&self) -> &[Arc<LiteServer>] {
+        &self.servers
+    }
+
+    pub fn max_unreliability(&self) -> usize {
+        self.max_unreliability.load(Ordering::Acquire)
+    }
+
+    pub fn set_max_unreliability(&self, max_unreliability: usize) {
+        self.max_unreliability
+            .store(max_unreliability, Ordering::Release);
+    }
+
+    /// Returns `true` if at least one lite server is considered healthy
+    pub fn is_ok(&self) -> bool {
+        let max_unreliability = self.max_unreliability();
+        self.servers
+            .iter()
+            .any(|server| server.is_ok(max_unreliability))
+    }
+
+    /// Acquires connection to the next healthy lite server in round-robin order.
+    /// Unhealthy servers are used only when all healthy ones are unavailable.
+    pub async fn acquire_connection(&self) -> QueryResult<AdnlConnection> {
+        let offset = self.next_server.fetch_add(1, Ordering::Relaxed);
+        let server_count = self.servers.len();
+        let max_unreliability = self.max_unreliability();
+
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) = (0..server_count)
+            .map(|i| &self.servers[(offset + i) % server_count])
+            .partition(|server| server.is_ok(max_unreliability));
+
+        for server in healthy.into_iter().chain(unhealthy) {
+            match server.pool.get_owned().await {
+                Ok(connection) => {
+                    return Ok(AdnlConnection {
+                        server: server.clone(),
+                        connection,
+                    })
+                }
+                Err(e) => {
+                    log::error!("connection error ({}): {:#?}", server.address, e);
+                }
+            }
+        }
+
+        Err(QueryError::ConnectionError)
+    }
+}
+
+pub struct LiteServer {
+    address: SocketAddrV4,
+    pool: Pool<AdnlManageConnection>,
+    unreliability: Arc<AtomicUsize>,
+}
+
+impl LiteServer {
+    pub fn address(&self) -> &SocketAddrV4 {
+        &self.address
+    }
+
+    pub fn unreliability(&self) -> usize {
+        self.unreliability.load(Ordering::Acquire)
+    }
+
+    pub fn is_ok(&self, max_unreliability: usize) -> bool {
+        self.unreliability() <= max_unreliability
+    }
+
+    pub fn bump_unreliability(&self, points: usize) {
+        self.unreliability.fetch_add(points, Ordering::Release);
+    }
+
+    pub fn pool_state(&self) -> bb8::State {
+        self.pool.state()
+    }
+}
+
+/// Pooled connection which remembers the lite server it belongs to
+pub struct AdnlConnection {
+    server: Arc<LiteServer>,
+    connection: PooledConnection<'static, AdnlManageConnection>,
+}
+
+impl AdnlConnection {
+    pub fn server(&self) -> &Arc<LiteServer> {
+        &self.server
+    }
+}
+
+impl Deref for AdnlConnection {
+    type Target = PooledConnection<'static, AdnlManageConnection>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.connection
+    }
+}
+
+impl DerefMut for AdnlConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.connection
+    }
+}
+
 pub struct AdnlManageConnection {
     config: AdnlTcpClientConfig,
     unreliability: Arc<AtomicUsize>,
@@ -36,7 +191,10 @@ impl bb8::ManageConnection for AdnlManageConnection {
     type Error = Error;
 
     async fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        log::debug!("Establishing adnl connection...");
+        log::debug!(
+            "Establishing adnl connection to {}...",
+            self.config.server_address
+        );
         match AdnlTcpClient::connect(self.config.clone()).await {
             Ok(connection) => {
                 // Note: don't reset unreliability here, make sure that `ping` will be successful
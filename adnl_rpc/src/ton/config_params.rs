@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+
+use ton_block::{ConfigParamEnum, HashmapType};
+use ton_types::SliceData;
+
+use adnl_rpc_models::{
+    ConfigParam, GasPrices, MsgForwardPrices, ValidatorDescription, ValidatorSet,
+    WorkchainDescription,
+};
+
+use super::errors::*;
+
+/// Decodes the specified params, params which are missing in the config are skipped
+pub fn decode_config_params(
+    config: &ton_block::ConfigParams,
+    params: &[u32],
+) -> QueryResult<BTreeMap<u32, ConfigParam>> {
+    let mut result = BTreeMap::new();
+    for &index in params {
+        if let Some(param) = decode_config_param(config, index)? {
+            result.insert(index, param);
+        }
+    }
+    Ok(result)
+}
+
+fn decode_config_param(
+    config: &ton_block::ConfigParams,
+    index: u32,
+) -> QueryResult<Option<ConfigParam>> {
+    let param = match index {
+        12 | 20 | 21 | 24 | 25 | 32 | 34 | 36 => {
+            config.config(index).map_err(|_| QueryError::InvalidBlock)?
+        }
+        _ => return read_raw_config_param(config, index),
+    };
+
+    Ok(match param {
+        Some(ConfigParamEnum::ConfigParam12(param)) => Some(ConfigParam::Workchains(
+            convert_workchains(&param.workchains)?,
+        )),
+        Some(ConfigParamEnum::ConfigParam20(prices))
+        | Some(ConfigParamEnum::ConfigParam21(prices)) => {
+            Some(ConfigParam::GasPrices(convert_gas_prices(&prices)))
+        }
+        Some(ConfigParamEnum::ConfigParam24(prices))
+        | Some(ConfigParamEnum::ConfigParam25(prices)) => Some(ConfigParam::MsgForwardPrices(
+            convert_msg_forward_prices(&prices),
+        )),
+        Some(ConfigParamEnum::ConfigParam32(param)) => Some(ConfigParam::ValidatorSet(
+            convert_validator_set(&param.prev_validators),
+        )),
+        Some(ConfigParamEnum::ConfigParam34(param)) => Some(ConfigParam::ValidatorSet(
+            convert_validator_set(&param.cur_validators),
+        )),
+        Some(ConfigParamEnum::ConfigParam36(param)) => Some(ConfigParam::ValidatorSet(
+            convert_validator_set(&param.next_validators),
+        )),
+        _ => None,
+    })
+}
+
+fn read_raw_config_param(
+    config: &ton_block::ConfigParams,
+    index: u32,
+) -> QueryResult<Option<ConfigParam>> {
+    let key = SliceData::from_raw(index.to_be_bytes().to_vec(), 32);
+    match config
+        .config_params
+        .get(key)
+        .map_err(|_| QueryError::InvalidBlock)?
+    {
+        Some(value) => value
+            .reference(0)
+            .map(|cell| Some(ConfigParam::Raw(cell)))
+            .map_err(|_| QueryError::InvalidBlock),
+        None => Ok(None),
+    }
+}
+
+fn convert_workchains(
+    workchains: &ton_block::Workchains,
+) -> QueryResult<Vec<WorkchainDescription>> {
+    let mut result = Vec::new();
+    workchains
+        .iterate_with_keys(|workchain: i32, descr: ton_block::WorkchainDescr| {
+            result.push(WorkchainDescription {
+                workchain,
+                enabled_since: descr.enabled_since,
+                actual_min_split: descr.actual_min_split(),
+                min_split: descr.min_split(),
+                max_split: descr.max_split(),
+                active: descr.active,
+                accept_msgs: descr.accept_msgs,
+                zerostate_root_hash: descr.zerostate_root_hash,
+                zerostate_file_hash: descr.zerostate_file_hash,
+                version: descr.version,
+            });
+            Ok(true)
+        })
+        .map_err(|_| QueryError::InvalidBlock)?;
+    Ok(result)
+}
+
+fn convert_gas_prices(prices: &ton_block::GasLimitsPrices) -> GasPrices {
+    GasPrices {
+        gas_price: prices.gas_price,
+        gas_limit: prices.gas_limit,
+        special_gas_limit: prices.special_gas_limit,
+        gas_credit: prices.gas_credit,
+        block_gas_limit: prices.block_gas_limit,
+        freeze_due_limit: prices.freeze_due_limit,
+        delete_due_limit: prices.delete_due_limit,
+        flat_gas_limit: prices.flat_gas_limit,
+        flat_gas_price: prices.flat_gas_price,
+    }
+}
+
+fn convert_msg_forward_prices(prices: &ton_block::MsgForwardPrices) -> MsgForwardPrices {
+    MsgForwardPrices {
+        lump_price: prices.lump_price,
+        bit_price: prices.bit_price,
+        cell_price: prices.cell_price,
+        ihr_price_factor: prices.ihr_price_factor,
+        first_frac: prices.first_frac,
+        next_frac: prices.next_frac,
+    }
+}
+
+fn convert_validator_set(set: &ton_block::ValidatorSet) -> ValidatorSet {
+    ValidatorSet {
+        utime_since: set.utime_since(),
+        utime_until: set.utime_until(),
+        total: set.total(),
+        main: set.main(),
+        total_weight: set.total_weight(),
+        validators: set
+            .list()
+            .iter()
+            .map(|validator| ValidatorDescription {
+                public_key: hex::encode(validator.public_key.as_slice()),
+                weight: validator.weight,
+                adnl_addr: validator
+                    .adnl_addr
+                    .as_ref()
+                    .map(|addr| addr.to_hex_string()),
+            })
+            .collect(),
+    }
+}
@@ -1,27 +1,70 @@
-use bb8::{Pool, PooledConnection};
+use bb8::PooledConnection;
 use ton_api::ton;
 use ton_block::Deserializable;
+use ton_types::UInt256;
+
+use adnl_rpc_models::BlockIdExt;
 
 use super::errors::*;
+use crate::metrics;
 use crate::ton::adnl_pool::AdnlManageConnection;
 
+pub const MASTERCHAIN_SHARD: u64 = 0x8000000000000000;
+
 pub async fn query_block_by_seqno(
     connection: &mut PooledConnection<'_, AdnlManageConnection>,
     id: ton::ton_node::blockid::BlockId,
 ) -> QueryResult<ton_block::Block> {
-    let block_id = query(
+    let seqno = id.seqno as u32;
+    let block_id = lookup_block(
+        connection,
+        id.workchain,
+        id.shard as u64,
+        BlockLookup::Seqno(seqno),
+    )
+    .await?;
+
+    query_block(connection, block_id).await
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum BlockLookup {
+    Seqno(u32),
+    Lt(u64),
+    Utime(u32),
+}
+
+/// Finds the block in the shard which has the specified seqno or
+/// contains the specified logical time or unix time
+pub async fn lookup_block(
+    connection: &mut PooledConnection<'_, AdnlManageConnection>,
+    workchain: i32,
+    shard: u64,
+    lookup: BlockLookup,
+) -> QueryResult<ton::ton_node::blockidext::BlockIdExt> {
+    let (mode, seqno, lt, utime) = match lookup {
+        BlockLookup::Seqno(seqno) => (0x1, seqno, None, None),
+        BlockLookup::Lt(lt) => (0x2, 0, Some(lt as i64), None),
+        BlockLookup::Utime(utime) => (0x4, 0, None, Some(utime as i32)),
+    };
+
+    let block_header = query(
         connection,
         &ton::rpc::lite_server::LookupBlock {
-            mode: 0x1,
-            id,
-            lt: None,
-            utime: None,
+            mode,
+            id: ton::ton_node::blockid::BlockId {
+                workchain,
+                shard: shard as i64,
+                seqno: seqno as i32,
+            },
+            lt,
+            utime,
         },
     )
     .await?
     .try_into_data()?;
 
-    query_block(connection, block_id.only().id).await
+    Ok(block_header.only().id)
 }
 
 pub async fn query_block(
@@ -38,6 +81,72 @@ pub async fn query_block(
     Ok(block)
 }
 
+/// Returns ids of the top shard blocks committed in the masterchain block
+pub fn shard_block_ids(
+    mc_block: &ton_block::Block,
+) -> QueryResult<Vec<ton::ton_node::blockidext::BlockIdExt>> {
+    let mut ids = Vec::new();
+    if let Some(mc_extra) = mc_block
+        .read_extra()
+        .and_then(|extra| extra.read_custom())
+        .map_err(|_| QueryError::InvalidBlock)?
+    {
+        mc_extra
+            .shards()
+            .iterate_shards(|ident, descr| {
+                ids.push(ton::ton_node::blockidext::BlockIdExt {
+                    workchain: ident.workchain_id(),
+                    shard: ident.shard_prefix_with_tag() as i64,
+                    seqno: descr.seq_no as i32,
+                    root_hash: ton::int256(descr.root_hash.into()),
+                    file_hash: ton::int256(descr.file_hash.into()),
+                });
+                Ok(true)
+            })
+            .map_err(|_| QueryError::InvalidBlock)?;
+    }
+    Ok(ids)
+}
+
+/// Extracts the blockchain config from the key block
+pub fn read_config_params(key_block: &ton_block::Block) -> QueryResult<ton_block::ConfigParams> {
+    key_block
+        .read_extra()
+        .and_then(|extra| extra.read_custom())
+        .map_err(|_| QueryError::InvalidBlock)?
+        .and_then(|mc_extra| mc_extra.config().cloned())
+        .ok_or(QueryError::InvalidBlock)
+}
+
+pub fn make_account_id(
+    address: &ton_block::MsgAddressInt,
+) -> ton::lite_server::accountid::AccountId {
+    ton::lite_server::accountid::AccountId {
+        workchain: address.workchain_id(),
+        id: ton::int256(UInt256::from(address.address().get_bytestring(0)).into()),
+    }
+}
+
+pub fn convert_block_id(id: &ton::ton_node::blockidext::BlockIdExt) -> BlockIdExt {
+    BlockIdExt {
+        workchain: id.workchain,
+        shard: id.shard as u64,
+        seqno: id.seqno as u32,
+        root_hash: UInt256::from(id.root_hash.0),
+        file_hash: UInt256::from(id.file_hash.0),
+    }
+}
+
+pub fn make_block_id(id: &BlockIdExt) -> ton::ton_node::blockidext::BlockIdExt {
+    ton::ton_node::blockidext::BlockIdExt {
+        workchain: id.workchain,
+        shard: id.shard as i64,
+        seqno: id.seqno as i32,
+        root_hash: ton::int256(id.root_hash.into()),
+        file_hash: ton::int256(id.file_hash.into()),
+    }
+}
+
 pub async fn query<T>(
     connection: &mut PooledConnection<'_, AdnlManageConnection>,
     query: &T,
@@ -70,6 +179,7 @@ where
             Err(error) => match error.downcast::<ton::lite_server::Error>() {
                 Ok(error) if error.code() == &ERR_NOT_READY => {
                     if retries < MAX_RETIRES {
+                        metrics::NOT_READY_RETRIES.inc();
                         tokio::time::sleep(std::time::Duration::from_millis(RETRY_INTERVAL)).await;
                         retries += 1;
                         continue;
@@ -84,15 +194,6 @@ where
     }
 }
 
-pub async fn acquire_connection(
-    pool: &Pool<AdnlManageConnection>,
-) -> QueryResult<PooledConnection<'_, AdnlManageConnection>> {
-    pool.get().await.map_err(|e| {
-        log::error!("connection error: {:#?}", e);
-        QueryError::ConnectionError
-    })
-}
-
 pub enum QueryReply<T> {
     Data(T),
     NotReady,
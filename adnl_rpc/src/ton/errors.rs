@@ -12,8 +12,30 @@ pub enum QueryError {
     LiteServer(ton::lite_server::Error),
     #[error("Invalid account state proof")]
     InvalidAccountStateProof,
+    #[error("Account state proof doesn't match the requested block")]
+    AccountStateProofMismatch,
+    #[error("Invalid config proof")]
+    InvalidConfigProof,
+    #[error("Config proof doesn't match the requested block")]
+    ConfigProofMismatch,
     #[error("Invalid block")]
     InvalidBlock,
+    #[error("Invalid VM stack")]
+    InvalidStack,
+    #[error("Invalid ABI: {0}")]
+    InvalidAbi(String),
+    #[error("Account not found")]
+    AccountNotFound,
+    #[error("Execution failed: {0}")]
+    ExecutionFailed(String),
+    #[error("Message expired")]
+    MessageExpired,
+    #[error("Invalid message")]
+    InvalidMessage,
+    #[error("Transaction not found")]
+    TransactionNotFound,
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
     #[error("Unknown")]
     Unknown,
     #[error("Not ready")]
@@ -29,7 +51,34 @@ impl QueryError {
             QueryError::InvalidAccountStateProof => -32004,
             QueryError::InvalidBlock => -32006,
             QueryError::NotReady => -32007,
+            QueryError::AccountStateProofMismatch => -32008,
+            QueryError::InvalidStack => -32009,
+            QueryError::InvalidAbi(_) => -32010,
+            QueryError::AccountNotFound => -32011,
+            QueryError::ExecutionFailed(_) => -32012,
+            QueryError::MessageExpired => -32013,
+            QueryError::InvalidMessage => -32014,
+            QueryError::TransactionNotFound => -32015,
+            QueryError::InvalidConfig(_) => -32016,
+            QueryError::InvalidConfigProof => -32017,
+            QueryError::ConfigProofMismatch => -32018,
             QueryError::Unknown => -32603,
         }
     }
+
+    /// Returns `true` if the error is caused by the lite server itself, so the query
+    /// may succeed on another one. Lite server errors describe the query and are
+    /// returned as is
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            QueryError::ConnectionError
+                | QueryError::Unknown
+                | QueryError::NotReady
+                | QueryError::InvalidAccountStateProof
+                | QueryError::AccountStateProofMismatch
+                | QueryError::InvalidConfigProof
+                | QueryError::ConfigProofMismatch
+        )
+    }
 }
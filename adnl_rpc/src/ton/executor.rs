@@ -0,0 +1,195 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use ton_block::{Deserializable, Serializable};
+use ton_executor::{
+    BlockchainConfig, ExecuteParams, OrdinaryTransactionExecutor, TransactionExecutor,
+};
+use ton_types::{Cell, SliceData};
+use ton_vm::executor::gas::gas_state::Gas;
+use ton_vm::executor::Engine;
+use ton_vm::smart_contract_info::SmartContractInfo;
+use ton_vm::stack::savelist::SaveList;
+use ton_vm::stack::{Stack, StackItem};
+
+use adnl_rpc_models::{ActionPhase, ComputePhase, ComputeSkipReason};
+
+use super::errors::*;
+
+/// Executes the message against the account locally, the account cell is updated in place
+pub fn execute_message(
+    config: &ton_block::ConfigParams,
+    account: &mut Cell,
+    message: &ton_block::Message,
+    utime: u32,
+    lt: u64,
+) -> QueryResult<ton_block::Transaction> {
+    let config = BlockchainConfig::with_config(config.clone()).map_err(execution_error)?;
+
+    OrdinaryTransactionExecutor::new(config)
+        .execute_with_params(
+            Some(message),
+            account,
+            ExecuteParams {
+                block_unixtime: utime,
+                block_lt: lt,
+                last_tr_lt: Arc::new(AtomicU64::new(lt)),
+                ..Default::default()
+            },
+        )
+        .map_err(execution_error)
+}
+
+/// Runs the external message in TVM without creating a transaction, so getters
+/// which don't accept the message can be called. Returns the exit code and out messages
+pub fn run_external_message(
+    config: &ton_block::ConfigParams,
+    account: &ton_block::Account,
+    message: &ton_block::Message,
+    utime: u32,
+    lt: u64,
+) -> QueryResult<(i32, Vec<ton_block::Message>)> {
+    let (code, data, address, balance) = match (
+        account.get_code(),
+        account.get_data(),
+        account.get_addr(),
+        account.balance(),
+    ) {
+        (Some(code), Some(data), Some(address), Some(balance)) => (code, data, address, balance),
+        _ => return Err(QueryError::AccountNotFound),
+    };
+
+    let mut ctrls = SaveList::new();
+    ctrls
+        .put(4, &mut StackItem::Cell(data))
+        .map_err(execution_error)?;
+
+    let mut info = SmartContractInfo::with_myself(
+        address
+            .serialize()
+            .map_err(|_| QueryError::FailedToSerialize)?
+            .into(),
+    );
+    *info.block_lt_mut() = lt;
+    *info.trans_lt_mut() = lt;
+    *info.unix_time_mut() = utime;
+    *info.balance_remaining_grams_mut() = balance.grams.0;
+    *info.balance_remaining_other_mut() = balance.other_as_hashmap();
+    if let Some(config_params) = config.config_params.data() {
+        info.set_config_params(config_params.clone());
+    }
+    ctrls
+        .put(7, &mut info.into_temp_data())
+        .map_err(execution_error)?;
+
+    let message_cell = message
+        .serialize()
+        .map_err(|_| QueryError::FailedToSerialize)?;
+
+    let mut stack = Stack::new();
+    stack
+        .push(ton_vm::int!(balance.grams.0))
+        .push(ton_vm::int!(0))
+        .push(StackItem::Cell(message_cell))
+        .push(StackItem::Slice(message.body().unwrap_or_default()))
+        .push(ton_vm::int!(-1)); // External message selector
+
+    let mut engine = Engine::new().setup_with_libraries(
+        SliceData::from(code),
+        Some(ctrls),
+        Some(stack),
+        Some(Gas::new(LOCAL_GAS_LIMIT, 0, LOCAL_GAS_LIMIT, 10)),
+        Vec::new(),
+    );
+
+    let exit_code = match engine.execute() {
+        Ok(exit_code) => exit_code,
+        Err(e) => return Ok((ton_vm::error::tvm_exception_or_custom_code(&e), Vec::new())),
+    };
+
+    let mut messages = Vec::new();
+    if let Ok(actions) = engine.get_actions().as_cell() {
+        let actions =
+            ton_block::OutActions::construct_from_cell(actions.clone()).map_err(execution_error)?;
+        for action in actions {
+            if let ton_block::OutAction::SendMsg { out_msg, .. } = action {
+                messages.push(out_msg);
+            }
+        }
+    }
+
+    Ok((exit_code, messages))
+}
+
+pub fn read_ordinary_description(
+    transaction: &ton_block::Transaction,
+) -> QueryResult<ton_block::TransactionDescrOrdinary> {
+    match transaction.read_description() {
+        Ok(ton_block::TransactionDescr::Ordinary(description)) => Ok(description),
+        _ => Err(QueryError::ExecutionFailed(
+            "Unexpected transaction type".to_owned(),
+        )),
+    }
+}
+
+pub fn read_out_messages(
+    transaction: &ton_block::Transaction,
+) -> QueryResult<Vec<ton_block::Message>> {
+    let mut messages = Vec::new();
+    transaction
+        .out_msgs
+        .iterate_slices(|slice| {
+            let message = ton_block::Message::construct_from_cell(slice.reference(0)?)?;
+            messages.push(message);
+            Ok(true)
+        })
+        .map_err(execution_error)?;
+    Ok(messages)
+}
+
+pub fn convert_compute_phase(phase: &ton_block::TrComputePhase) -> ComputePhase {
+    match phase {
+        ton_block::TrComputePhase::Skipped(skipped) => ComputePhase::Skipped {
+            reason: match skipped.reason {
+                ton_block::ComputeSkipReason::NoState => ComputeSkipReason::NoState,
+                ton_block::ComputeSkipReason::BadState => ComputeSkipReason::BadState,
+                ton_block::ComputeSkipReason::NoGas => ComputeSkipReason::NoGas,
+            },
+        },
+        ton_block::TrComputePhase::Vm(phase) => ComputePhase::Vm {
+            success: phase.success,
+            exit_code: phase.exit_code,
+            gas_fees: grams(&phase.gas_fees),
+            gas_used: phase.gas_used.0 as u64,
+            vm_steps: phase.vm_steps,
+        },
+    }
+}
+
+pub fn convert_action_phase(phase: &ton_block::TrActionPhase) -> ActionPhase {
+    ActionPhase {
+        success: phase.success,
+        valid: phase.valid,
+        no_funds: phase.no_funds,
+        result_code: phase.result_code,
+        total_fwd_fees: phase.total_fwd_fees.as_ref().map(grams).unwrap_or_default(),
+        total_action_fees: phase
+            .total_action_fees
+            .as_ref()
+            .map(grams)
+            .unwrap_or_default(),
+        total_actions: phase.tot_actions,
+        messages_created: phase.msgs_created,
+    }
+}
+
+fn execution_error<E: std::fmt::Display>(error: E) -> QueryError {
+    QueryError::ExecutionFailed(error.to_string())
+}
+
+pub fn grams(value: &ton_block::Grams) -> u128 {
+    value.0
+}
+
+/// Gas limit for the local getter calls
+const LOCAL_GAS_LIMIT: i64 = 1_000_000_000;
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+
+use ton_api::ton;
+use ton_block::{
+    BlkPrevInfo, ExtBlkRef, HashmapAugType, InRefValue, MsgAddressInt, Serializable, ShardIdent,
+};
+
+use adnl_rpc_models::{RawTransaction, WsResponseMessage};
+
+use super::adnl_pool::AdnlConnection;
+use super::connection::*;
+use super::errors::*;
+use super::{AddressSubscriptionsMap, State};
+
+/// Walks new masterchain and shard blocks and delivers transactions to account subscribers
+#[derive(Default)]
+pub struct Indexer {
+    last_mc_seqno: Option<u32>,
+    shard_tops: ShardTops,
+}
+
+impl Indexer {
+    pub async fn process_new_blocks(&mut self, state: &State) -> QueryResult<()> {
+        if state.address_subscriptions.read().await.is_empty() {
+            // Start from the latest block when someone subscribes
+            *self = Default::default();
+            return Ok(());
+        }
+
+        let last_block_id = state
+            .with_failover(|mut connection| async move {
+                state.last_block.get_last_block(&mut connection).await
+            })
+            .await?;
+
+        let last_mc_seqno = last_block_id.seqno as u32;
+        let first_mc_seqno = match self.last_mc_seqno {
+            Some(seqno) => seqno + 1,
+            None => {
+                // Shard blocks of the first processed masterchain block are walked
+                // back to the shard blocks of the previous one
+                self.shard_tops = state
+                    .with_failover(|connection| {
+                        fetch_shard_tops(connection, last_mc_seqno.saturating_sub(1))
+                    })
+                    .await?;
+                last_mc_seqno
+            }
+        };
+
+        for mc_seqno in first_mc_seqno..=last_mc_seqno {
+            let known_tops = &self.shard_tops;
+            let (blocks, shard_tops) = state
+                .with_failover(|connection| fetch_new_blocks(connection, mc_seqno, known_tops))
+                .await?;
+
+            let subscriptions = state.address_subscriptions.read().await;
+            for block in &blocks {
+                if let Err(e) = notify_subscribers(&subscriptions, block) {
+                    log::error!("Failed to process block transactions: {}", e);
+                }
+            }
+
+            self.shard_tops = shard_tops;
+            self.last_mc_seqno = Some(mc_seqno);
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetches root hashes of the shard blocks committed in the masterchain block
+async fn fetch_shard_tops(mut connection: AdnlConnection, mc_seqno: u32) -> QueryResult<ShardTops> {
+    let mc_block = query_mc_block(&mut connection, mc_seqno).await?;
+    Ok(shard_block_ids(&mc_block)?
+        .into_iter()
+        .map(|id| id.root_hash.0)
+        .collect())
+}
+
+/// Fetches masterchain block with the specified seqno and all shard blocks
+/// which were committed since the previous masterchain block
+async fn fetch_new_blocks(
+    mut connection: AdnlConnection,
+    mc_seqno: u32,
+    known_tops: &ShardTops,
+) -> QueryResult<(Vec<ton_block::Block>, ShardTops)> {
+    let mc_block = query_mc_block(&mut connection, mc_seqno).await?;
+
+    let top_blocks = shard_block_ids(&mc_block)?;
+    let shard_tops = top_blocks.iter().map(|id| id.root_hash.0).collect();
+
+    // Every shard chain leads to one of the known top blocks, following
+    // both parents on merge and the parent shard on split
+    let mut shard_blocks = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = top_blocks;
+    while let Some(block_id) = pending.pop() {
+        if known_tops.contains(&block_id.root_hash.0) || !visited.insert(block_id.root_hash.0) {
+            continue;
+        }
+
+        if shard_blocks.len() >= MAX_SHARD_BLOCKS {
+            log::warn!(
+                "More than {} shard blocks were committed in masterchain block {}, \
+                 transactions of the older ones are skipped",
+                MAX_SHARD_BLOCKS,
+                mc_seqno
+            );
+            break;
+        }
+
+        let block = query_block(&mut connection, block_id).await?;
+        let info = block
+            .info
+            .read_struct()
+            .map_err(|_| QueryError::InvalidBlock)?;
+
+        pending.extend(prev_block_ids(&info)?);
+        shard_blocks.push((info.seq_no(), block));
+    }
+
+    // Parents always have lower seqno than their children
+    shard_blocks.sort_by_key(|(seqno, _)| *seqno);
+
+    let mut blocks = vec![mc_block];
+    blocks.extend(shard_blocks.into_iter().map(|(_, block)| block));
+
+    Ok((blocks, shard_tops))
+}
+
+async fn query_mc_block(
+    connection: &mut AdnlConnection,
+    mc_seqno: u32,
+) -> QueryResult<ton_block::Block> {
+    query_block_by_seqno(
+        connection,
+        ton::ton_node::blockid::BlockId {
+            workchain: -1,
+            shard: MASTERCHAIN_SHARD as i64,
+            seqno: mc_seqno as i32,
+        },
+    )
+    .await
+}
+
+fn prev_block_ids(
+    info: &ton_block::BlockInfo,
+) -> QueryResult<Vec<ton::ton_node::blockidext::BlockIdExt>> {
+    let make_id = |shard: &ShardIdent, prev: &ExtBlkRef| ton::ton_node::blockidext::BlockIdExt {
+        workchain: shard.workchain_id(),
+        shard: shard.shard_prefix_with_tag() as i64,
+        seqno: prev.seq_no as i32,
+        root_hash: ton::int256(prev.root_hash.clone().into()),
+        file_hash: ton::int256(prev.file_hash.clone().into()),
+    };
+
+    let shard = info.shard();
+    match info.read_prev_ref().map_err(|_| QueryError::InvalidBlock)? {
+        BlkPrevInfo::Block { prev } if info.after_split() => {
+            let parent = shard.merge().map_err(|_| QueryError::InvalidBlock)?;
+            Ok(vec![make_id(&parent, &prev)])
+        }
+        BlkPrevInfo::Block { prev } => Ok(vec![make_id(shard, &prev)]),
+        BlkPrevInfo::Blocks { prev1, prev2 } => {
+            let (left, right) = shard.split().map_err(|_| QueryError::InvalidBlock)?;
+            let prev1 = prev1.read_struct().map_err(|_| QueryError::InvalidBlock)?;
+            let prev2 = prev2.read_struct().map_err(|_| QueryError::InvalidBlock)?;
+            Ok(vec![make_id(&left, &prev1), make_id(&right, &prev2)])
+        }
+    }
+}
+
+fn notify_subscribers(
+    subscriptions: &AddressSubscriptionsMap,
+    block: &ton_block::Block,
+) -> QueryResult<()> {
+    let workchain = block
+        .info
+        .read_struct()
+        .map_err(|_| QueryError::InvalidBlock)?
+        .shard()
+        .workchain_id();
+
+    let account_blocks = block
+        .read_extra()
+        .and_then(|extra| extra.read_account_blocks())
+        .map_err(|_| QueryError::InvalidBlock)?;
+
+    account_blocks
+        .iterate_objects(|account_block| {
+            let address = MsgAddressInt::with_standart(
+                None,
+                workchain as i8,
+                account_block.account_id().clone(),
+            )?;
+
+            let subscribers = match subscriptions.get(&address) {
+                Some(subscribers) => subscribers,
+                None => return Ok(true),
+            };
+
+            account_block
+                .transactions()
+                .iterate_objects(|InRefValue(transaction)| {
+                    let hash = transaction.serialize()?.repr_hash();
+                    let message =
+                        WsResponseMessage::Transaction(serde_json::to_value(RawTransaction {
+                            hash,
+                            data: transaction,
+                        })?);
+
+                    for tx in subscribers.values() {
+                        let _ = tx.unbounded_send(message.clone());
+                    }
+                    Ok(true)
+                })
+        })
+        .map_err(|_| QueryError::InvalidBlock)?;
+
+    Ok(())
+}
+
+/// Root hashes of the shard blocks committed in the last processed masterchain block
+type ShardTops = HashSet<[u8; 32]>;
+
+/// Max number of shard blocks processed for one masterchain block
+const MAX_SHARD_BLOCKS: usize = 256;
@@ -3,7 +3,9 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
 use bb8::PooledConnection;
+use tokio::sync::broadcast;
 use ton_api::ton;
+use ton_api::ton::lite_server::masterchaininfo::MasterchainInfo;
 use ton_api::ton::ton_node::blockidext::BlockIdExt;
 
 use super::adnl_pool::AdnlManageConnection;
@@ -14,39 +16,76 @@ pub struct LastBlock {
     state: parking_lot::RwLock<LastBlockState>,
     threshold: Duration,
     in_process: AtomicBool,
+    new_blocks: broadcast::Sender<BlockIdExt>,
 }
 
 impl LastBlock {
     pub fn new(threshold: &Duration) -> Self {
+        let (new_blocks, _) = broadcast::channel(MAX_ENQUEUED_BLOCKS);
+
         Self {
             state: parking_lot::RwLock::new(LastBlockState::new()),
             threshold: *threshold,
             in_process: AtomicBool::new(false),
+            new_blocks,
         }
     }
 
+    /// Subscribes for new masterchain blocks
+    pub fn subscribe(&self) -> broadcast::Receiver<BlockIdExt> {
+        self.new_blocks.subscribe()
+    }
+
+    /// Seqno of the latest known masterchain block
+    pub fn last_seqno(&self) -> Option<u32> {
+        self.state
+            .read()
+            .blocks
+            .front()
+            .map(|block| block.seqno as u32)
+    }
+
     pub async fn last_cached_blocks(&self) -> impl Iterator<Item = BlockIdExt> {
         self.state.read().blocks.clone().into_iter()
     }
 
+    /// Returns the last block id if it was received recently enough
+    pub fn cached_last_block(&self) -> Option<BlockIdExt> {
+        match &self.state.read().info {
+            Some((info, updated_at)) if updated_at.elapsed() < self.threshold => {
+                Some(info.last.clone())
+            }
+            _ => None,
+        }
+    }
+
     pub async fn get_last_block(
         &self,
         connection: &mut PooledConnection<'_, AdnlManageConnection>,
     ) -> QueryResult<ton::ton_node::blockidext::BlockIdExt> {
+        self.get_masterchain_info(connection)
+            .await
+            .map(|info| info.last)
+    }
+
+    pub async fn get_masterchain_info(
+        &self,
+        connection: &mut PooledConnection<'_, AdnlManageConnection>,
+    ) -> QueryResult<MasterchainInfo> {
         let now = {
             let state = self.state.read();
 
             let now = Instant::now();
 
-            match &state.id {
-                Some((result, last)) => {
+            match &state.info {
+                Some((info, last)) => {
                     if now.duration_since(*last) < self.threshold
                         || self
                             .in_process
                             .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
                             .is_err()
                     {
-                        return result.clone();
+                        return Ok(info.clone());
                     }
                     now
                 }
@@ -56,45 +95,52 @@ impl LastBlock {
 
         log::debug!("Getting mc block");
 
-        let id = query(connection, &ton::rpc::lite_server::GetMasterchainInfo)
+        let info = query(connection, &ton::rpc::lite_server::GetMasterchainInfo)
             .await
             .and_then(QueryReply::try_into_data)
-            .map(|result| result.only().last);
+            .map(|result| result.only());
 
         log::debug!("Got mc block");
 
         let mut state = self.state.write();
 
-        state.id = Some((id.clone(), now));
+        // Errors are not cached, so that the next query goes to another lite server
+        if let Ok(info) = &info {
+            state.info = Some((info.clone(), now));
+        }
 
-        if let Ok(new_id) = &id {
+        if let Ok(MasterchainInfo { last: new_id, .. }) = &info {
             match state.blocks.front() {
                 Some(latest_id) if new_id.seqno > latest_id.seqno => {
                     if state.blocks.len() >= MAX_ENQUEUED_BLOCKS {
                         state.blocks.pop_back();
                     }
                     state.blocks.push_front(new_id.clone());
+                    let _ = self.new_blocks.send(new_id.clone());
+                }
+                None => {
+                    state.blocks.push_front(new_id.clone());
+                    let _ = self.new_blocks.send(new_id.clone());
                 }
-                None => state.blocks.push_front(new_id.clone()),
                 _ => {}
             }
         }
 
         self.in_process.store(false, Ordering::Release);
 
-        id
+        info
     }
 }
 
 struct LastBlockState {
-    id: Option<(QueryResult<BlockIdExt>, Instant)>,
+    info: Option<(MasterchainInfo, Instant)>,
     blocks: VecDeque<BlockIdExt>,
 }
 
 impl LastBlockState {
     fn new() -> Self {
         Self {
-            id: None,
+            info: None,
             blocks: VecDeque::with_capacity(MAX_ENQUEUED_BLOCKS),
         }
     }
@@ -1,84 +1,277 @@
-use std::collections::HashMap;
-use std::convert::TryInto;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
-use bb8::{Pool, PooledConnection};
 use futures::channel::mpsc;
-use futures::StreamExt;
-use tokio::sync::RwLock;
+use futures::{future, stream, StreamExt};
+use lru::LruCache;
+use tokio::sync::{broadcast, oneshot, watch, Notify, OnceCell, RwLock};
 use ton_api::ton;
-use ton_block::{Deserializable, MsgAddressInt, Serializable};
+use ton_block::{Deserializable, HashmapAugType, MsgAddressInt, Serializable};
+use ton_types::UInt256;
 use warp::filters::ws;
 use warp::filters::ws::WebSocket;
 
 use adnl_rpc_models::{
-    ExistingContract, GenTimings, RawBlock, RawContractState, RawTransactionsList, TransactionId,
-    WsRequestMessage, WsResponseMessage,
+    BlockTransaction, BlockTransactionCursor, ConfigSource, DecodedTransactionsList,
+    EmulateMessageResponse, ExistingContract, GenTimings, GetBlockTransactionsResponse,
+    GetConfigParamsResponse, GetMethodId, GetShardsResponse, HealthcheckResponse, LastBlockStatus,
+    LiteServerStatus, LookupBlockResponse, MasterchainInfo, NewBlock, RawBlock, RawContractState,
+    RawMessage, RawTransaction, RawTransactionsList, RunGetMethodResponse, RunLocalResponse,
+    SendMessageAndWaitResponse, ShardDescription, StackItem, Subscription, TransactionId,
+    TransactionWithBlockId, WorkchainShards, WsRequestMessage, WsResponseMessage, ZeroStateIdExt,
 };
 
 use crate::config::Config;
+use crate::metrics;
 
-use self::adnl_pool::AdnlManageConnection;
+use self::adnl_pool::{AdnlConnection, AdnlPool};
+use self::config_params::decode_config_params;
+pub use self::connection::BlockLookup;
 use self::connection::*;
 pub use self::errors::*;
+use self::executor::*;
+use self::indexer::Indexer;
 use self::last_block::LastBlock;
+use self::proofs::{check_account_state_proof, check_config_proof};
+use self::transactions::decode_transactions;
+use self::vm_stack::{compute_method_id, deserialize_stack, serialize_stack};
 
+mod abi;
 mod adnl_pool;
+mod config_params;
 mod connection;
 mod errors;
+mod executor;
+mod indexer;
 mod last_block;
+mod proofs;
+mod transactions;
+mod vm_stack;
 
 static CONNECTION_ID: AtomicUsize = AtomicUsize::new(0);
 
+/// Tracks the active WebSocket connection, so that shutdown can wait for it
+struct WebSocketGuard<'a>(&'a State);
+
+impl<'a> WebSocketGuard<'a> {
+    fn new(state: &'a State) -> Self {
+        state.active_websockets.fetch_add(1, Ordering::AcqRel);
+        Self(state)
+    }
+}
+
+impl Drop for WebSocketGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.active_websockets.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.websockets_closed.notify_waiters();
+        }
+    }
+}
+
+/// WebSocket close code for the server going down
+const WS_GOING_AWAY: u16 = 1001;
+
 pub struct State {
-    pool: Pool<AdnlManageConnection>,
+    pool: parking_lot::RwLock<Arc<AdnlPool>>,
     last_block: LastBlock,
+    /// Latest key block with its seqno, updated by the masterchain cache updater
+    last_key_block: parking_lot::RwLock<Option<(u32, ton_block::Block)>>,
+    /// `None` if the cache is disabled
+    contract_states: Option<parking_lot::Mutex<ContractStatesCache>>,
+    block_transactions: parking_lot::Mutex<BlockTransactionsCache>,
     address_subscriptions: RwLock<AddressSubscriptionsMap>,
-    max_unreliability: usize,
-    unreliability: Arc<AtomicUsize>,
-    max_time_diff: u32,
+    block_subscriptions: RwLock<BlockSubscriptionsMap>,
+    max_time_diff: AtomicU32,
     time_diff: AtomicU32,
+    /// Seqno and generation time of the last masterchain block, updated by
+    /// the masterchain cache updater
+    last_block_utime: parking_lot::RwLock<Option<(u32, u32)>>,
+    indexer_interval: Duration,
+    message_wait_timeout: Duration,
+    shutdown_tx: watch::Sender<bool>,
+    shutdown_rx: watch::Receiver<bool>,
+    active_websockets: AtomicUsize,
+    websockets_closed: Notify,
 }
 
 impl State {
     pub async fn new(config: Config) -> Result<Self> {
-        let max_unreliability = config.max_unreliability;
-        let unreliability = Arc::new(AtomicUsize::new(0));
-
-        let max_time_diff = config.max_time_diff;
-
-        let builder = Pool::builder();
-        let pool = builder
-            .max_size(config.max_connection_count)
-            .min_idle(config.min_idle_connection_count)
-            .max_lifetime(None)
-            .build(AdnlManageConnection::new(
-                config.adnl_config.try_into()?,
-                unreliability.clone(),
-            ))
-            .await?;
+        let pool = AdnlPool::new(&config)?;
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
         Ok(Self {
-            pool,
+            pool: parking_lot::RwLock::new(Arc::new(pool)),
             last_block: LastBlock::new(&config.last_block_cache_duration),
+            last_key_block: Default::default(),
+            contract_states: match config.contract_states_cache_size {
+                0 => None,
+                size => Some(parking_lot::Mutex::new(LruCache::new(size))),
+            },
+            block_transactions: parking_lot::Mutex::new(LruCache::new(
+                BLOCK_TRANSACTIONS_CACHE_SIZE,
+            )),
             address_subscriptions: Default::default(),
-            max_unreliability,
-            unreliability,
-            max_time_diff,
+            block_subscriptions: Default::default(),
+            max_time_diff: AtomicU32::new(config.max_time_diff),
             time_diff: AtomicU32::new(0),
+            last_block_utime: Default::default(),
+            indexer_interval: config.indexer_interval,
+            message_wait_timeout: config.message_wait_timeout,
+            shutdown_tx,
+            shutdown_rx,
+            active_websockets: AtomicUsize::new(0),
+            websockets_closed: Notify::new(),
         })
     }
 
+    /// Stops background tasks and closes WebSocket connections
+    pub fn shutdown(&self) {
+        let _ = self.shutdown_tx.send(true);
+    }
+
+    pub async fn wait_for_shutdown(&self) {
+        let mut shutdown = self.shutdown_rx.clone();
+        while !*shutdown.borrow() {
+            if shutdown.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Waits until WebSocket connections send their remaining messages and
+    /// closes lite server connections. Must be called after the shutdown
+    pub async fn close(&self, timeout: Duration) {
+        if tokio::time::timeout(timeout, self.wait_for_websockets())
+            .await
+            .is_err()
+        {
+            log::warn!("WebSocket connections didn't close in {:?}", timeout);
+        }
+
+        let pool = std::mem::replace(&mut *self.pool.write(), Arc::new(AdnlPool::empty()));
+        match Arc::try_unwrap(pool) {
+            Ok(pool) => {
+                std::mem::drop(pool);
+                log::info!("Closed lite server connections");
+            }
+            Err(_) => log::warn!("Lite server connections will be closed when released"),
+        }
+    }
+
+    async fn wait_for_websockets(&self) {
+        loop {
+            // Subscribed before checking, so that the notification is not missed
+            let closed = self.websockets_closed.notified();
+            if self.active_websockets.load(Ordering::Acquire) == 0 {
+                break;
+            }
+            closed.await;
+        }
+    }
+
+    /// Applies the reloaded config. Lite server pools are replaced only if requested,
+    /// connections of the old pools are closed when they are released
+    pub async fn apply_config(&self, config: &Config, rebuild_pool: bool) -> Result<()> {
+        if rebuild_pool {
+            let pool = AdnlPool::new(config)?;
+            *self.pool.write() = Arc::new(pool);
+        } else {
+            self.pool().set_max_unreliability(config.max_unreliability);
+        }
+
+        self.max_time_diff
+            .store(config.max_time_diff, Ordering::Release);
+        Ok(())
+    }
+
     pub fn is_ok(&self) -> bool {
-        self.unreliability.load(Ordering::Acquire) <= self.max_unreliability
-            && self.time_diff.load(Ordering::Acquire) <= self.max_time_diff
+        self.pool().is_ok()
+            && self.time_diff.load(Ordering::Acquire) <= self.max_time_diff.load(Ordering::Acquire)
+    }
+
+    pub fn healthcheck(&self) -> HealthcheckResponse {
+        let pool = self.pool();
+        let max_unreliability = pool.max_unreliability();
+
+        let servers = pool
+            .servers()
+            .iter()
+            .map(|server| {
+                let pool_state = server.pool_state();
+                LiteServerStatus {
+                    address: server.address().to_string(),
+                    ok: server.is_ok(max_unreliability),
+                    unreliability: server.unreliability(),
+                    connections: pool_state.connections,
+                    idle_connections: pool_state.idle_connections,
+                }
+            })
+            .collect();
+
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let last_block = self
+            .last_block_utime
+            .read()
+            .map(|(seqno, gen_utime)| LastBlockStatus {
+                seqno,
+                age_ms: std::cmp::max(timestamp - gen_utime as i64 * 1000, 0) as u64,
+            });
+
+        HealthcheckResponse {
+            ok: self.is_ok(),
+            timestamp,
+            time_diff: self.time_diff.load(Ordering::Acquire),
+            max_time_diff: self.max_time_diff.load(Ordering::Acquire),
+            max_unreliability,
+            last_block,
+            servers,
+        }
+    }
+
+    /// Updates gauges which are computed from the current state
+    pub async fn update_metrics(&self) {
+        for server in self.pool().servers() {
+            let address = server.address().to_string();
+            let pool_state = server.pool_state();
+
+            metrics::POOL_CONNECTIONS
+                .with_label_values(&[&address])
+                .set(pool_state.connections as i64);
+            metrics::POOL_IDLE_CONNECTIONS
+                .with_label_values(&[&address])
+                .set(pool_state.idle_connections as i64);
+            metrics::UNRELIABILITY
+                .with_label_values(&[&address])
+                .set(server.unreliability() as i64);
+        }
+
+        metrics::TIME_DIFF.set(self.time_diff.load(Ordering::Acquire) as i64);
+        if let Some(seqno) = self.last_block.last_seqno() {
+            metrics::LAST_MC_SEQNO.set(seqno as i64);
+        }
+
+        let account_subscriptions: usize = self
+            .address_subscriptions
+            .read()
+            .await
+            .values()
+            .map(HashMap::len)
+            .sum();
+        metrics::WS_SUBSCRIPTIONS
+            .with_label_values(&["account"])
+            .set(account_subscriptions as i64);
+        metrics::WS_SUBSCRIPTIONS
+            .with_label_values(&["new_block"])
+            .set(self.block_subscriptions.read().await.len() as i64);
     }
 
     pub fn start_masterchain_cache_updater(self: &Arc<Self>) {
         let state = Arc::downgrade(self);
+        let mut shutdown = self.shutdown_rx.clone();
 
         tokio::spawn(async move {
             while let Some(state) = state.upgrade() {
@@ -88,14 +281,70 @@ impl State {
 
                 std::mem::drop(state);
 
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                    _ = shutdown.changed() => break,
+                }
             }
+            log::debug!("Masterchain cache updater stopped");
         });
     }
 
-    pub async fn send_message(&self, message: ton_block::Message) -> QueryResult<()> {
-        let mut connection = self.acquire_connection().await?;
+    pub fn start_block_notifier(self: &Arc<Self>) {
+        let state = Arc::downgrade(self);
+        let mut new_blocks = self.last_block.subscribe();
+        let mut shutdown = self.shutdown_rx.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let block_id = match tokio::select! {
+                    block_id = new_blocks.recv() => block_id,
+                    _ = shutdown.changed() => break,
+                } {
+                    Ok(block_id) => block_id,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("Skipped {} masterchain blocks", skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                let state = match state.upgrade() {
+                    Some(state) => state,
+                    None => break,
+                };
 
+                if let Err(e) = state.notify_new_block(block_id).await {
+                    log::error!("Failed to notify about new block: {}", e);
+                }
+            }
+        });
+    }
+
+    pub fn start_indexer(self: &Arc<Self>) {
+        let state = Arc::downgrade(self);
+        let interval = self.indexer_interval;
+        let mut shutdown = self.shutdown_rx.clone();
+
+        tokio::spawn(async move {
+            let mut indexer = Indexer::default();
+
+            while let Some(state) = state.upgrade() {
+                if let Err(e) = indexer.process_new_blocks(&state).await {
+                    log::error!("Failed to process new blocks: {}", e);
+                }
+
+                std::mem::drop(state);
+
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = shutdown.changed() => break,
+                }
+            }
+        });
+    }
+
+    pub async fn send_message(&self, message: ton_block::Message) -> QueryResult<()> {
         let cells = message
             .write_to_new_cell()
             .map_err(|_| QueryError::FailedToSerialize)?
@@ -104,35 +353,206 @@ impl State {
         let serialized =
             ton_types::serialize_toc(&cells).map_err(|_| QueryError::FailedToSerialize)?;
 
-        query(
-            &mut connection,
-            &ton::rpc::lite_server::SendMessage {
-                body: ton::bytes(serialized),
-            },
-        )
-        .await?
-        .try_into_data()?;
+        self.with_failover(|mut connection| {
+            let body = ton::bytes(serialized.clone());
+            async move {
+                query(
+                    &mut connection,
+                    &ton::rpc::lite_server::SendMessage { body },
+                )
+                .await?
+                .try_into_data()?;
 
-        Ok(())
+                Ok(())
+            }
+        })
+        .await
     }
 
+    /// Sends the message and waits until the transaction with it appears
+    /// on the destination account. The expiration is read from the message
+    /// header if the ABI is specified, `expire_at` is used otherwise
+    pub async fn send_message_and_wait(
+        &self,
+        message: ton_block::Message,
+        abi: Option<String>,
+        expire_at: Option<u32>,
+    ) -> QueryResult<SendMessageAndWaitResponse> {
+        const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+        let address = message.dst().ok_or(QueryError::InvalidMessage)?;
+        let message_hash = message
+            .serialize()
+            .map_err(|_| QueryError::FailedToSerialize)?
+            .repr_hash();
+
+        let mut known_lt = match self.get_contract_state(address.clone()).await? {
+            RawContractState::Exists(contract) => contract.last_transaction_id.lt,
+            RawContractState::NotExists => 0,
+        };
+
+        let expire_at = match &abi {
+            Some(abi) => abi::read_expire(abi, &message)?.or(expire_at),
+            None => expire_at,
+        };
+
+        let deadline = {
+            let timeout =
+                chrono::Utc::now().timestamp() as u64 + self.message_wait_timeout.as_secs();
+            match expire_at {
+                Some(expire_at) => std::cmp::min(expire_at as u64, timeout),
+                None => timeout,
+            }
+        };
+
+        self.send_message(message).await?;
+
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+
+            // Deadline is checked after the last poll, so that the transaction
+            // which appeared right before the expiration is not missed
+            let expired = chrono::Utc::now().timestamp() as u64 > deadline;
+
+            if let RawContractState::Exists(contract) =
+                self.get_contract_state(address.clone()).await?
+            {
+                let last_transaction_id = contract.last_transaction_id;
+                if last_transaction_id.lt > known_lt {
+                    if let Some(transaction) = self
+                        .find_transaction(&address, last_transaction_id, known_lt, &message_hash)
+                        .await?
+                    {
+                        return Ok(SendMessageAndWaitResponse {
+                            message_hash,
+                            transaction,
+                        });
+                    }
+                    known_lt = last_transaction_id.lt;
+                }
+            }
+
+            if expired {
+                return Err(QueryError::MessageExpired);
+            }
+        }
+    }
+
+    /// Searches the transaction by the hash of its inbound message,
+    /// walking from the specified transaction back to `until_lt`
+    async fn find_transaction(
+        &self,
+        address: &MsgAddressInt,
+        from: TransactionId,
+        until_lt: u64,
+        message_hash: &UInt256,
+    ) -> QueryResult<Option<RawTransaction>> {
+        const BATCH_SIZE: u8 = 16;
+
+        let mut from = Some(from);
+        while let Some(id) = from.take() {
+            if id.lt <= until_lt {
+                break;
+            }
+
+            let transactions = self
+                .get_transactions(address.clone(), Some(id), BATCH_SIZE)
+                .await?;
+            let roots = ton_types::deserialize_cells_tree(&mut std::io::Cursor::new(
+                &transactions.transactions,
+            ))
+            .map_err(|_| QueryError::InvalidBlock)?;
+
+            for root in roots {
+                let hash = root.repr_hash();
+                let transaction = ton_block::Transaction::construct_from_cell(root)
+                    .map_err(|_| QueryError::InvalidBlock)?;
+                if transaction.lt <= until_lt {
+                    return Ok(None);
+                }
+
+                if transaction
+                    .in_msg_cell()
+                    .map(|cell| cell.repr_hash())
+                    .as_ref()
+                    == Some(message_hash)
+                {
+                    return Ok(Some(RawTransaction {
+                        hash,
+                        data: transaction,
+                    }));
+                }
+
+                from = match transaction.prev_trans_lt {
+                    0 => None,
+                    lt => Some(TransactionId {
+                        lt,
+                        hash: transaction.prev_trans_hash,
+                    }),
+                };
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the contract state in the last masterchain block. States are cached
+    /// per block and concurrent requests for the same state share one query
     pub async fn get_contract_state(
         &self,
         address: MsgAddressInt,
     ) -> QueryResult<RawContractState> {
-        use ton_block::HashmapAugType;
+        self.get_contract_state_with_timings(address)
+            .await
+            .map(|(state, _)| state)
+    }
 
-        let mut connection = self.acquire_connection().await?;
-        let last_block_id = self.last_block.get_last_block(&mut connection).await?;
+    /// Returns the contract state together with the timings of the shard state
+    /// it was read from. The timings are known even if the contract doesn't exist
+    async fn get_contract_state_with_timings(
+        &self,
+        address: MsgAddressInt,
+    ) -> QueryResult<(RawContractState, GenTimings)> {
+        let last_block_id = self.get_last_block_id().await?;
 
+        let cell = match &self.contract_states {
+            Some(contract_states) => {
+                let mut contract_states = contract_states.lock();
+                let key = (
+                    address.clone(),
+                    last_block_id.seqno,
+                    UInt256::from(last_block_id.root_hash.0),
+                );
+                match contract_states.get(&key) {
+                    Some(cell) => cell.clone(),
+                    None => {
+                        let cell = Arc::new(OnceCell::new());
+                        contract_states.put(key, cell.clone());
+                        cell
+                    }
+                }
+            }
+            None => Arc::new(OnceCell::new()),
+        };
+
+        cell.get_or_try_init(|| {
+            self.with_failover(|connection| {
+                self.query_contract_state(connection, &address, &last_block_id)
+            })
+        })
+        .await
+        .map(Clone::clone)
+    }
+
+    async fn query_contract_state(
+        &self,
+        mut connection: AdnlConnection,
+        address: &MsgAddressInt,
+        last_block_id: &ton::ton_node::blockidext::BlockIdExt,
+    ) -> QueryResult<(RawContractState, GenTimings)> {
         let mut account_state_query = ton::rpc::lite_server::GetAccountState {
             id: last_block_id.clone(),
-            account: ton::lite_server::accountid::AccountId {
-                workchain: address.workchain_id(),
-                id: ton::int256(
-                    ton_types::UInt256::from(address.address().get_bytestring(0)).into(),
-                ),
-            },
+            account: make_account_id(address),
         };
 
         let response = {
@@ -161,49 +581,156 @@ impl State {
         }
         .only();
 
-        match ton_block::Account::construct_from_bytes(&response.state.0) {
-            Ok(ton_block::Account::Account(account)) => {
-                let q_roots =
-                    ton_types::deserialize_cells_tree(&mut std::io::Cursor::new(&response.proof.0))
-                        .map_err(|_| QueryError::InvalidAccountStateProof)?;
-                if q_roots.len() != 2 {
-                    return Err(QueryError::InvalidAccountStateProof);
-                }
+        let (ss, shard_info) =
+            check_account_state_proof(&account_state_query.id, address, &response)?;
 
-                let merkle_proof = ton_block::MerkleProof::construct_from_cell(q_roots[1].clone())
-                    .map_err(|_| QueryError::InvalidAccountStateProof)?;
-                let proof_root = merkle_proof.proof.virtualize(1);
-
-                let ss = ton_block::ShardStateUnsplit::construct_from(&mut proof_root.into())
-                    .map_err(|_| QueryError::InvalidAccountStateProof)?;
-
-                let shard_info = ss
-                    .read_accounts()
-                    .and_then(|accounts| {
-                        accounts.get(&ton_types::UInt256::from(
-                            address.get_address().get_bytestring(0),
-                        ))
-                    })
-                    .map_err(|_| QueryError::InvalidAccountStateProof)?;
-
-                Ok(if let Some(shard_info) = shard_info {
-                    RawContractState::Exists(ExistingContract {
-                        account,
-                        timings: GenTimings {
-                            gen_lt: ss.gen_lt(),
-                            gen_utime: (chrono::Utc::now().timestamp() - 10) as u32, // TEMP!!!!!, replace with ss.gen_time(),
-                        },
-                        last_transaction_id: TransactionId {
-                            lt: shard_info.last_trans_lt(),
-                            hash: *shard_info.last_trans_hash(),
+        let timings = GenTimings {
+            gen_lt: ss.gen_lt(),
+            gen_utime: ss.gen_time(),
+        };
+
+        let state = match ton_block::Account::construct_from_bytes(&response.state.0) {
+            Ok(ton_block::Account::Account(account)) => match shard_info {
+                Some(shard_info) => RawContractState::Exists(ExistingContract {
+                    account,
+                    timings,
+                    last_transaction_id: TransactionId {
+                        lt: shard_info.last_trans_lt(),
+                        hash: *shard_info.last_trans_hash(),
+                    },
+                }),
+                None => RawContractState::NotExists,
+            },
+            _ => RawContractState::NotExists,
+        };
+
+        Ok((state, timings))
+    }
+
+    pub async fn run_get_method(
+        &self,
+        address: MsgAddressInt,
+        method: GetMethodId,
+        stack: Vec<StackItem>,
+    ) -> QueryResult<RunGetMethodResponse> {
+        let method_id = compute_method_id(&method);
+        let params = serialize_stack(&stack)?;
+
+        let result = self
+            .with_failover(|mut connection| {
+                let address = &address;
+                let params = ton::bytes(params.clone());
+                async move {
+                    let last_block_id = self.last_block.get_last_block(&mut connection).await?;
+
+                    query(
+                        &mut connection,
+                        &ton::rpc::lite_server::RunSmcMethod {
+                            mode: 0x4,
+                            id: last_block_id,
+                            account: make_account_id(address),
+                            method_id,
+                            params,
                         },
-                    })
-                } else {
-                    RawContractState::NotExists
+                    )
+                    .await?
+                    .try_into_data()
+                }
+            })
+            .await?
+            .only();
+
+        let stack = match result.result {
+            Some(result) => deserialize_stack(&result.0)?,
+            None => Vec::new(),
+        };
+
+        Ok(RunGetMethodResponse {
+            exit_code: result.exit_code,
+            stack,
+        })
+    }
+
+    pub async fn run_local(
+        &self,
+        address: MsgAddressInt,
+        abi: String,
+        method: String,
+        input: serde_json::Value,
+    ) -> QueryResult<RunLocalResponse> {
+        let function = abi::load_function(&abi, &method)?;
+        let message = abi::encode_external_call(&function, address.clone(), input)?;
+
+        let contract = match self.get_contract_state(address).await? {
+            RawContractState::Exists(contract) => contract,
+            RawContractState::NotExists => return Err(QueryError::AccountNotFound),
+        };
+        let config = self.get_blockchain_config().await?;
+
+        let (exit_code, out_messages) = run_external_message(
+            &config,
+            &ton_block::Account::Account(contract.account),
+            &message,
+            contract.timings.gen_utime,
+            contract.timings.gen_lt,
+        )?;
+
+        Ok(RunLocalResponse {
+            aborted: exit_code != 0 && exit_code != 1,
+            exit_code,
+            output: abi::decode_output(&function, &out_messages)?,
+        })
+    }
+
+    /// Executes the message against the current state of the destination account
+    /// without broadcasting it
+    pub async fn emulate_message(
+        &self,
+        message: ton_block::Message,
+    ) -> QueryResult<EmulateMessageResponse> {
+        let address = message.dst().ok_or(QueryError::InvalidMessage)?;
+
+        let (state, timings) = self.get_contract_state_with_timings(address).await?;
+        let account = match state {
+            RawContractState::Exists(contract) => ton_block::Account::Account(contract.account),
+            RawContractState::NotExists => ton_block::Account::AccountNone,
+        };
+        let config = self.get_blockchain_config().await?;
+
+        let mut account = account
+            .serialize()
+            .map_err(|_| QueryError::FailedToSerialize)?;
+
+        let transaction = execute_message(
+            &config,
+            &mut account,
+            &message,
+            timings.gen_utime,
+            timings.gen_lt,
+        )?;
+        let description = read_ordinary_description(&transaction)?;
+
+        let out_messages = read_out_messages(&transaction)?
+            .into_iter()
+            .map(|message| {
+                let hash = message
+                    .serialize()
+                    .map_err(|_| QueryError::FailedToSerialize)?
+                    .repr_hash();
+                Ok(RawMessage {
+                    hash,
+                    data: message,
                 })
-            }
-            _ => Ok(RawContractState::NotExists),
-        }
+            })
+            .collect::<QueryResult<Vec<_>>>()?;
+
+        Ok(EmulateMessageResponse {
+            aborted: description.aborted,
+            compute_phase: convert_compute_phase(&description.compute_ph),
+            action_phase: description.action.as_ref().map(convert_action_phase),
+            total_fees: grams(&transaction.total_fees().grams),
+            out_messages,
+        })
     }
 
     pub async fn get_transactions(
@@ -212,48 +739,423 @@ impl State {
         from: Option<TransactionId>,
         count: u8,
     ) -> QueryResult<RawTransactionsList> {
-        let mut connection = self.acquire_connection().await?;
+        let transactions = match self.query_transactions(address, from, count).await? {
+            Some(transactions) => transactions,
+            None => ton_types::serialize_toc(&ton_types::Cell::default()).unwrap(),
+        };
+
+        Ok(RawTransactionsList { transactions })
+    }
 
+    pub async fn get_decoded_transactions(
+        &self,
+        address: MsgAddressInt,
+        from: Option<TransactionId>,
+        count: u8,
+    ) -> QueryResult<DecodedTransactionsList> {
+        let transactions = match self.query_transactions(address, from, count).await? {
+            Some(transactions) => decode_transactions(&transactions)?,
+            None => Vec::new(),
+        };
+
+        Ok(DecodedTransactionsList { transactions })
+    }
+
+    /// Fetches the transaction from the specified block
+    pub async fn get_transaction_by_block(
+        &self,
+        address: MsgAddressInt,
+        lt: u64,
+        block_id: adnl_rpc_models::BlockIdExt,
+    ) -> QueryResult<TransactionWithBlockId> {
+        let block_id = make_block_id(&block_id);
+
+        let response = self
+            .with_failover(|mut connection| {
+                let query_data = ton::rpc::lite_server::GetOneTransaction {
+                    id: block_id.clone(),
+                    account: make_account_id(&address),
+                    lt: lt as i64,
+                };
+                async move { query(&mut connection, &query_data).await?.try_into_data() }
+            })
+            .await?
+            .only();
+
+        Ok(TransactionWithBlockId {
+            block_id: convert_block_id(&response.id),
+            transaction: read_single_transaction(&response.transaction.0, None)?,
+        })
+    }
+
+    /// Fetches the transaction by its id, the block is taken from the lite server response
+    pub async fn get_transaction_by_id(
+        &self,
+        address: MsgAddressInt,
+        id: TransactionId,
+    ) -> QueryResult<TransactionWithBlockId> {
+        let response = self
+            .with_failover(|mut connection| {
+                let query_data = ton::rpc::lite_server::GetTransactions {
+                    count: 1,
+                    account: make_account_id(&address),
+                    lt: id.lt as i64,
+                    hash: id.hash.into(),
+                };
+                async move { query(&mut connection, &query_data).await?.try_into_data() }
+            })
+            .await?
+            .only();
+
+        let block_id = response
+            .ids
+            .first()
+            .ok_or(QueryError::TransactionNotFound)?;
+
+        Ok(TransactionWithBlockId {
+            block_id: convert_block_id(block_id),
+            transaction: read_single_transaction(&response.transactions.0, Some(&id.hash))?,
+        })
+    }
+
+    /// Returns the BOC with transactions, `None` if the account doesn't exist
+    async fn query_transactions(
+        &self,
+        address: MsgAddressInt,
+        from: Option<TransactionId>,
+        count: u8,
+    ) -> QueryResult<Option<Vec<u8>>> {
         let from = match from {
             Some(id) => id,
             None => match self.get_contract_state(address.clone()).await? {
                 RawContractState::Exists(contract) => contract.last_transaction_id,
-                RawContractState::NotExists => {
-                    let transactions =
-                        ton_types::serialize_toc(&ton_types::Cell::default()).unwrap();
+                RawContractState::NotExists => return Ok(None),
+            },
+        };
 
-                    return Ok(RawTransactionsList { transactions });
+        let transactions_query = ton::rpc::lite_server::GetTransactions {
+            count: count as i32,
+            account: make_account_id(&address),
+            lt: from.lt as i64,
+            hash: from.hash.into(),
+        };
+
+        let response = self
+            .with_failover(|mut connection| {
+                let transactions_query = &transactions_query;
+                async move {
+                    query(&mut connection, transactions_query)
+                        .await?
+                        .try_into_data()
                 }
+            })
+            .await?;
+
+        Ok(Some(response.only().transactions.0))
+    }
+
+    pub async fn lookup_block(
+        &self,
+        workchain: i32,
+        shard: u64,
+        lookup: BlockLookup,
+        with_block: bool,
+    ) -> QueryResult<LookupBlockResponse> {
+        self.with_failover(|mut connection| async move {
+            let block_id = lookup_block(&mut connection, workchain, shard, lookup).await?;
+
+            let block = if with_block {
+                let block = query_block(&mut connection, block_id.clone()).await?;
+                Some(RawBlock { block })
+            } else {
+                None
+            };
+
+            Ok(LookupBlockResponse {
+                id: convert_block_id(&block_id),
+                block,
+            })
+        })
+        .await
+    }
+
+    pub async fn get_masterchain_info(&self) -> QueryResult<MasterchainInfo> {
+        let info = self
+            .with_failover(|mut connection| async move {
+                self.last_block.get_masterchain_info(&mut connection).await
+            })
+            .await?;
+
+        Ok(MasterchainInfo {
+            last: convert_block_id(&info.last),
+            state_root_hash: UInt256::from(info.state_root_hash.0),
+            init: ZeroStateIdExt {
+                workchain: info.init.workchain,
+                root_hash: UInt256::from(info.init.root_hash.0),
+                file_hash: UInt256::from(info.init.file_hash.0),
             },
+            time_diff: self.time_diff.load(Ordering::Acquire),
+        })
+    }
+
+    pub async fn get_shards(&self) -> QueryResult<GetShardsResponse> {
+        let response = self
+            .with_failover(|mut connection| async move {
+                let last_block_id = self.last_block.get_last_block(&mut connection).await?;
+
+                query(
+                    &mut connection,
+                    &ton::rpc::lite_server::GetAllShardsInfo { id: last_block_id },
+                )
+                .await?
+                .try_into_data()
+            })
+            .await?
+            .only();
+
+        let shard_hashes = ton_block::ShardHashes::construct_from_bytes(&response.data.0)
+            .map_err(|_| QueryError::InvalidBlock)?;
+
+        let mut workchains: Vec<WorkchainShards> = Vec::new();
+        shard_hashes
+            .iterate_shards(|ident, descr| {
+                let shard = ShardDescription {
+                    id: adnl_rpc_models::BlockIdExt {
+                        workchain: ident.workchain_id(),
+                        shard: ident.shard_prefix_with_tag(),
+                        seqno: descr.seq_no,
+                        root_hash: descr.root_hash,
+                        file_hash: descr.file_hash,
+                    },
+                    gen_utime: descr.gen_utime,
+                    before_split: descr.before_split,
+                    before_merge: descr.before_merge,
+                    want_split: descr.want_split,
+                    want_merge: descr.want_merge,
+                };
+
+                match workchains.last_mut() {
+                    Some(last) if last.workchain == ident.workchain_id() => last.shards.push(shard),
+                    _ => workchains.push(WorkchainShards {
+                        workchain: ident.workchain_id(),
+                        shards: vec![shard],
+                    }),
+                }
+                Ok(true)
+            })
+            .map_err(|_| QueryError::InvalidBlock)?;
+
+        Ok(GetShardsResponse {
+            mc_block_id: convert_block_id(&response.id),
+            workchains,
+        })
+    }
+
+    pub async fn get_block_transactions(
+        &self,
+        block_id: adnl_rpc_models::BlockIdExt,
+        after: Option<BlockTransactionCursor>,
+        count: u32,
+        with_data: bool,
+    ) -> QueryResult<GetBlockTransactionsResponse> {
+        const MODE_ACCOUNT: i32 = 0x1;
+        const MODE_LT: i32 = 0x2;
+        const MODE_HASH: i32 = 0x4;
+        const MODE_AFTER: i32 = 0x80;
+        // Lite servers don't return more transactions per query
+        const MAX_COUNT: u32 = 256;
+
+        let block_id = make_block_id(&block_id);
+        let count = std::cmp::min(count, MAX_COUNT) as i32;
+
+        let mut mode = MODE_ACCOUNT | MODE_LT | MODE_HASH;
+        let after = after.map(|after| {
+            mode |= MODE_AFTER;
+            ton::lite_server::transactionid3::TransactionId3 {
+                account: make_account_id(&after.account).id,
+                lt: after.lt as i64,
+            }
+        });
+
+        self.with_failover(|mut connection| {
+            let block_id = block_id.clone();
+            let after = after.clone();
+            async move {
+                let response = query(
+                    &mut connection,
+                    &ton::rpc::lite_server::ListBlockTransactions {
+                        id: block_id.clone(),
+                        mode,
+                        count,
+                        after,
+                        reverse_order: None,
+                        want_proof: None,
+                    },
+                )
+                .await?
+                .try_into_data()?
+                .only();
+
+                // Transactions are taken from the whole block instead of querying them one by one
+                let block_transactions = if with_data {
+                    Some(
+                        self.get_block_transactions_data(&mut connection, &response.id)
+                            .await?,
+                    )
+                } else {
+                    None
+                };
+
+                let mut transactions = Vec::with_capacity(response.ids.len());
+                for id in response.ids {
+                    let (account, lt, hash) = match (id.account, id.lt, id.hash) {
+                        (Some(account), Some(lt), Some(hash)) => (account, lt, hash),
+                        _ => return Err(QueryError::InvalidBlock),
+                    };
+
+                    let data = match &block_transactions {
+                        Some(block_transactions) => Some(
+                            block_transactions
+                                .get(&UInt256::from(hash.0))
+                                .cloned()
+                                .ok_or(QueryError::InvalidBlock)?,
+                        ),
+                        None => None,
+                    };
+
+                    transactions.push(BlockTransaction {
+                        account: MsgAddressInt::with_standart(
+                            None,
+                            response.id.workchain as i8,
+                            ton_types::SliceData::from_raw(account.0.to_vec(), 256),
+                        )
+                        .map_err(|_| QueryError::InvalidBlock)?,
+                        lt: lt as u64,
+                        hash: UInt256::from(hash.0),
+                        data,
+                    });
+                }
+
+                Ok(GetBlockTransactionsResponse {
+                    id: convert_block_id(&response.id),
+                    incomplete: matches!(response.incomplete, ton::Bool::BoolTrue),
+                    transactions,
+                })
+            }
+        })
+        .await
+    }
+
+    /// Returns cells of the block transactions by their hashes. Blocks are cached,
+    /// so that the paginated listing downloads each block once
+    async fn get_block_transactions_data(
+        &self,
+        connection: &mut AdnlConnection,
+        block_id: &ton::ton_node::blockidext::BlockIdExt,
+    ) -> QueryResult<Arc<HashMap<UInt256, ton_types::Cell>>> {
+        let key = UInt256::from(block_id.root_hash.0);
+        if let Some(transactions) = self.block_transactions.lock().get(&key) {
+            return Ok(transactions.clone());
+        }
+
+        let block = query_block(connection, block_id.clone()).await?;
+        let transactions = Arc::new(read_block_transactions(&block)?);
+        self.block_transactions
+            .lock()
+            .put(key, transactions.clone());
+        Ok(transactions)
+    }
+
+    pub async fn get_latest_key_block(&self) -> QueryResult<RawBlock> {
+        self.with_failover(|connection| self.query_latest_key_block(connection))
+            .await
+    }
+
+    pub async fn get_config_params(
+        &self,
+        source: ConfigSource,
+        params: Option<Vec<u32>>,
+    ) -> QueryResult<GetConfigParamsResponse> {
+        let config = match source {
+            ConfigSource::KeyBlock => self.get_blockchain_config().await?,
+            ConfigSource::LiteServer => self.query_config_params(params.as_deref()).await?,
         };
 
-        let response = query(
-            &mut connection,
-            &ton::rpc::lite_server::GetTransactions {
-                count: count as i32,
-                account: ton::lite_server::accountid::AccountId {
-                    workchain: address.workchain_id() as i32,
-                    id: ton::int256(
-                        ton_types::UInt256::from(address.address().get_bytestring(0)).into(),
-                    ),
-                },
-                lt: from.lt as i64,
-                hash: from.hash.into(),
+        Ok(match params {
+            Some(params) => GetConfigParamsResponse::Decoded {
+                params: decode_config_params(&config, &params)?,
+            },
+            None => GetConfigParamsResponse::Raw {
+                config: config
+                    .serialize()
+                    .map_err(|_| QueryError::FailedToSerialize)?,
             },
-        )
-        .await?
-        .try_into_data()?;
+        })
+    }
+
+    /// Requests the config from the latest masterchain state,
+    /// the whole config is requested if no params are specified
+    async fn query_config_params(
+        &self,
+        params: Option<&[u32]>,
+    ) -> QueryResult<ton_block::ConfigParams> {
+        self.with_failover(|mut connection| async move {
+            let last_block_id = self.last_block.get_last_block(&mut connection).await?;
+
+            let response = match params {
+                Some(params) => query(
+                    &mut connection,
+                    &ton::rpc::lite_server::GetConfigParams {
+                        mode: 0,
+                        id: last_block_id.clone(),
+                        param_list: params.iter().map(|&param| param as i32).collect(),
+                    },
+                )
+                .await?
+                .try_into_data()?,
+                None => query(
+                    &mut connection,
+                    &ton::rpc::lite_server::GetConfigAll {
+                        mode: 0,
+                        id: last_block_id.clone(),
+                    },
+                )
+                .await?
+                .try_into_data()?,
+            }
+            .only();
 
-        Ok(RawTransactionsList {
-            transactions: response.only().transactions.0,
+            check_config_proof(&last_block_id, &response)
         })
+        .await
     }
 
-    pub async fn get_latest_key_block(&self) -> QueryResult<RawBlock> {
-        const MASTERCHAIN_SHARD: u64 = 0x8000000000000000;
+    /// Returns the cached last block id, the connection is used only if it is outdated
+    async fn get_last_block_id(&self) -> QueryResult<ton::ton_node::blockidext::BlockIdExt> {
+        if let Some(block_id) = self.last_block.cached_last_block() {
+            return Ok(block_id);
+        }
+
+        self.with_failover(|mut connection| async move {
+            self.last_block.get_last_block(&mut connection).await
+        })
+        .await
+    }
 
-        let mut connection = self.acquire_connection().await?;
+    /// Returns the blockchain config from the cached key block
+    async fn get_blockchain_config(&self) -> QueryResult<ton_block::ConfigParams> {
+        let key_block = self.last_key_block.read().clone();
+        let key_block = match key_block {
+            Some((_, key_block)) => key_block,
+            None => self.get_latest_key_block().await?.block,
+        };
+        read_config_params(&key_block)
+    }
 
+    async fn query_latest_key_block(
+        &self,
+        mut connection: AdnlConnection,
+    ) -> QueryResult<RawBlock> {
         let last_block_id = self.last_block.get_last_block(&mut connection).await?;
 
         let block = query_block(&mut connection, last_block_id).await?;
@@ -268,35 +1170,129 @@ impl State {
             0,
         );
         self.time_diff.store(time_diff as u32, Ordering::Release);
+        *self.last_block_utime.write() = Some((info.seq_no(), info.gen_utime().0));
 
-        if info.key_block() {
-            Ok(RawBlock { block })
+        let key_block_seqno = if info.key_block() {
+            info.seq_no()
         } else {
-            let block = query_block_by_seqno(
-                &mut connection,
-                ton::ton_node::blockid::BlockId {
-                    workchain: -1,
-                    shard: MASTERCHAIN_SHARD as i64,
-                    seqno: info.prev_key_block_seqno() as i32,
-                },
-            )
+            info.prev_key_block_seqno()
+        };
+
+        // Key block is requested only when a new one appears
+        let cached = match &*self.last_key_block.read() {
+            Some((seqno, key_block)) if *seqno == key_block_seqno => Some(key_block.clone()),
+            _ => None,
+        };
+
+        let block = match cached {
+            Some(key_block) => return Ok(RawBlock { block: key_block }),
+            None if info.key_block() => block,
+            None => {
+                query_block_by_seqno(
+                    &mut connection,
+                    ton::ton_node::blockid::BlockId {
+                        workchain: -1,
+                        shard: MASTERCHAIN_SHARD as i64,
+                        seqno: key_block_seqno as i32,
+                    },
+                )
+                .await?
+            }
+        };
+
+        log::info!("New key block {}", key_block_seqno);
+        *self.last_key_block.write() = Some((key_block_seqno, block.clone()));
+
+        Ok(RawBlock { block })
+    }
+
+    async fn notify_new_block(
+        &self,
+        block_id: ton::ton_node::blockidext::BlockIdExt,
+    ) -> QueryResult<()> {
+        if self.block_subscriptions.read().await.is_empty() {
+            return Ok(());
+        }
+
+        let block = self
+            .with_failover(|mut connection| {
+                let block_id = block_id.clone();
+                async move { query_block(&mut connection, block_id).await }
+            })
             .await?;
-            Ok(RawBlock { block })
+
+        let info = block
+            .info
+            .read_struct()
+            .map_err(|_| QueryError::InvalidBlock)?;
+
+        let shard_block_ids = shard_block_ids(&block)?
+            .iter()
+            .map(convert_block_id)
+            .collect::<Vec<_>>();
+
+        let mut new_block = NewBlock {
+            id: convert_block_id(&block_id),
+            gen_utime: info.gen_utime().0,
+            is_key_block: info.key_block(),
+            shard_block_ids: None,
+        };
+        let message = WsResponseMessage::Block(new_block.clone());
+
+        new_block.shard_block_ids = Some(shard_block_ids);
+        let message_with_shards = WsResponseMessage::Block(new_block);
+
+        for subscription in self.block_subscriptions.read().await.values() {
+            let message = if subscription.with_shards {
+                &message_with_shards
+            } else {
+                &message
+            };
+            let _ = subscription.tx.unbounded_send(message.clone());
         }
+
+        Ok(())
     }
 
-    pub async fn handle_websocket(&self, websocket: WebSocket) {
+    /// Handles subscriptions of the WebSocket connection, the number of active
+    /// subscriptions is limited by the API key
+    pub async fn handle_websocket(&self, websocket: WebSocket, max_subscriptions: Option<usize>) {
         let (tx, rx) = mpsc::unbounded::<WsResponseMessage>();
         let (ws_tx, mut ws_rx) = websocket.split();
 
         let connection_id = CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        let _guard = WebSocketGuard::new(self);
+        let mut shutdown = self.shutdown_rx.clone();
+        let mut shutting_down = *shutdown.borrow();
+
+        let mut subscribed_accounts = HashSet::new();
+        let mut subscribed_for_new_blocks = false;
+        let limit_reached = |accounts: &HashSet<MsgAddressInt>, new_blocks: bool| {
+            let subscriptions = accounts.len() + new_blocks as usize;
+            matches!(max_subscriptions, Some(max) if subscriptions >= max)
+        };
 
-        tokio::task::spawn(
-            rx.map(|message| Ok(ws::Message::text(serde_json::to_string(&message).unwrap())))
+        // Close frame is sent after all messages if the server is shutting down
+        let (close_tx, close_rx) = oneshot::channel::<ws::Message>();
+        let forwarder = tokio::task::spawn(
+            rx.map(|message| ws::Message::text(serde_json::to_string(&message).unwrap()))
+                .chain(stream::once(close_rx).filter_map(|close| future::ready(close.ok())))
+                .map(Ok)
                 .forward(ws_tx),
         );
 
-        while let Some(Ok(message)) = ws_rx.next().await {
+        while !shutting_down {
+            let message = tokio::select! {
+                message = ws_rx.next() => match message {
+                    Some(Ok(message)) => message,
+                    _ => break,
+                },
+                _ = shutdown.changed() => {
+                    shutting_down = true;
+                    break;
+                }
+            };
+
             let message: WsRequestMessage = match message
                 .to_str()
                 .and_then(|s| serde_json::from_str::<WsRequestMessage>(s).map_err(|_| ()))
@@ -310,26 +1306,203 @@ impl State {
 
             log::debug!("Received {:?}", message);
 
-            match message {
+            let response = match message {
+                WsRequestMessage::SubscribeAccount { address }
+                    if !subscribed_accounts.contains(&address)
+                        && limit_reached(&subscribed_accounts, subscribed_for_new_blocks) =>
+                {
+                    WsResponseMessage::SubscriptionsLimitReached(Subscription::Account { address })
+                }
                 WsRequestMessage::SubscribeAccount { address } => {
+                    subscribed_accounts.insert(address.clone());
                     let mut addresses_callbacks = self.address_subscriptions.write().await;
                     addresses_callbacks
                         .entry(address.clone())
                         .or_insert_with(HashMap::new)
                         .insert(connection_id, tx.clone());
+                    WsResponseMessage::Subscribed(Subscription::Account { address })
                 }
-                WsRequestMessage::SubscribeForNewBlock => {}
+                WsRequestMessage::SubscribeForNewBlock(_)
+                    if !subscribed_for_new_blocks
+                        && limit_reached(&subscribed_accounts, subscribed_for_new_blocks) =>
+                {
+                    WsResponseMessage::SubscriptionsLimitReached(Subscription::NewBlock)
+                }
+                WsRequestMessage::SubscribeForNewBlock(params) => {
+                    subscribed_for_new_blocks = true;
+                    let params = params.unwrap_or_default();
+                    self.block_subscriptions.write().await.insert(
+                        connection_id,
+                        BlockSubscription {
+                            tx: tx.clone(),
+                            with_shards: params.with_shards,
+                        },
+                    );
+                    WsResponseMessage::Subscribed(Subscription::NewBlock)
+                }
+                WsRequestMessage::UnsubscribeAccount { address } => {
+                    subscribed_accounts.remove(&address);
+                    self.unsubscribe_account(connection_id, &address).await;
+                    WsResponseMessage::Unsubscribed(Subscription::Account { address })
+                }
+                WsRequestMessage::UnsubscribeFromNewBlock => {
+                    subscribed_for_new_blocks = false;
+                    self.block_subscriptions
+                        .write()
+                        .await
+                        .remove(&connection_id);
+                    WsResponseMessage::Unsubscribed(Subscription::NewBlock)
+                }
+                WsRequestMessage::UnsubscribeAll => {
+                    subscribed_accounts.clear();
+                    subscribed_for_new_blocks = false;
+                    self.unsubscribe_all(connection_id).await;
+                    WsResponseMessage::Unsubscribed(Subscription::All)
+                }
+            };
+
+            let _ = tx.unbounded_send(response);
+        }
+
+        if shutting_down {
+            let _ = close_tx.send(ws::Message::close_with(
+                WS_GOING_AWAY,
+                "Server is shutting down",
+            ));
+        } else {
+            drop(close_tx);
+        }
+
+        self.unsubscribe_all(connection_id).await;
+
+        // Forwarder finishes when all senders are dropped and the close frame is sent
+        drop(tx);
+        let _ = forwarder.await;
+
+        log::debug!("Websocket connection {} closed", connection_id);
+    }
+
+    async fn unsubscribe_account(&self, connection_id: usize, address: &MsgAddressInt) {
+        let mut addresses_callbacks = self.address_subscriptions.write().await;
+        if let Some(callbacks) = addresses_callbacks.get_mut(address) {
+            callbacks.remove(&connection_id);
+            if callbacks.is_empty() {
+                addresses_callbacks.remove(address);
             }
         }
     }
 
-    async fn acquire_connection(
-        &self,
-    ) -> Result<PooledConnection<'_, AdnlManageConnection>, QueryError> {
-        acquire_connection(&self.pool).await
+    async fn unsubscribe_all(&self, connection_id: usize) {
+        self.address_subscriptions
+            .write()
+            .await
+            .retain(|_, callbacks| {
+                callbacks.remove(&connection_id);
+                !callbacks.is_empty()
+            });
+        self.block_subscriptions
+            .write()
+            .await
+            .remove(&connection_id);
+    }
+
+    fn pool(&self) -> Arc<AdnlPool> {
+        self.pool.read().clone()
+    }
+
+    /// Runs the query on connections to different lite servers
+    /// until it succeeds or every server has been tried
+    async fn with_failover<F, R, T>(&self, mut f: F) -> QueryResult<T>
+    where
+        F: FnMut(AdnlConnection) -> R,
+        R: Future<Output = QueryResult<T>>,
+    {
+        let pool = self.pool();
+
+        let mut result = Err(QueryError::ConnectionError);
+        for _ in 0..pool.servers().len() {
+            let connection = pool.acquire_connection().await?;
+            let server = connection.server().clone();
+
+            result = f(connection).await;
+            match &result {
+                Err(e) if e.is_retryable() => {
+                    log::warn!(
+                        "Lite server {} failed ({}), trying another one",
+                        server.address(),
+                        e
+                    );
+                    server.bump_unreliability(1);
+                }
+                _ => break,
+            }
+        }
+        result
+    }
+}
+
+/// Returns the cells of all block transactions by their hashes
+fn read_block_transactions(
+    block: &ton_block::Block,
+) -> QueryResult<HashMap<UInt256, ton_types::Cell>> {
+    let account_blocks = block
+        .read_extra()
+        .and_then(|extra| extra.read_account_blocks())
+        .map_err(|_| QueryError::InvalidBlock)?;
+
+    let mut transactions = HashMap::new();
+    account_blocks
+        .iterate_objects(|account_block| {
+            account_block
+                .transactions()
+                .iterate_objects(|ton_block::InRefValue(transaction)| {
+                    let cell = transaction.serialize()?;
+                    transactions.insert(cell.repr_hash(), cell);
+                    Ok(true)
+                })
+        })
+        .map_err(|_| QueryError::InvalidBlock)?;
+
+    Ok(transactions)
+}
+
+/// Parses the transaction BOC and checks its hash if specified
+fn read_single_transaction(data: &[u8], hash: Option<&UInt256>) -> QueryResult<RawTransaction> {
+    if data.is_empty() {
+        return Err(QueryError::TransactionNotFound);
+    }
+
+    let cell = ton_types::deserialize_tree_of_cells(&mut std::io::Cursor::new(data))
+        .map_err(|_| QueryError::InvalidBlock)?;
+    let cell_hash = cell.repr_hash();
+    if matches!(hash, Some(hash) if hash != &cell_hash) {
+        return Err(QueryError::TransactionNotFound);
     }
+
+    Ok(RawTransaction {
+        hash: cell_hash,
+        data: ton_block::Transaction::construct_from_cell(cell)
+            .map_err(|_| QueryError::InvalidBlock)?,
+    })
 }
 
+/// Max number of blocks with transactions cached for `getBlockTransactions`
+const BLOCK_TRANSACTIONS_CACHE_SIZE: usize = 16;
+
+/// Transactions by their hashes, keyed by block root hash
+type BlockTransactionsCache = LruCache<UInt256, Arc<HashMap<UInt256, ton_types::Cell>>>;
+
+/// Contract states keyed by address and masterchain block seqno with root hash
+type ContractStatesCache =
+    LruCache<(MsgAddressInt, i32, UInt256), Arc<OnceCell<(RawContractState, GenTimings)>>>;
+
 type AddressSubscriptionsMap = HashMap<MsgAddressInt, HashMap<usize, WsTx>>;
 
+type BlockSubscriptionsMap = HashMap<usize, BlockSubscription>;
+
+struct BlockSubscription {
+    tx: WsTx,
+    with_shards: bool,
+}
+
 type WsTx = mpsc::UnboundedSender<WsResponseMessage>;
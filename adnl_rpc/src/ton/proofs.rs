@@ -0,0 +1,175 @@
+use ton_api::ton;
+use ton_block::{Deserializable, HashmapAugType, MsgAddressInt};
+use ton_types::{Cell, UInt256};
+
+use super::errors::*;
+
+type BlockIdExt = ton::ton_node::blockidext::BlockIdExt;
+
+/// Verifies the whole proof chain returned by `GetAccountState`:
+/// masterchain block -> shard block -> shard state -> account.
+///
+/// Returns the proven shard state and the account info, if the account exists
+pub fn check_account_state_proof(
+    mc_block_id: &BlockIdExt,
+    address: &MsgAddressInt,
+    response: &ton::lite_server::accountstate::AccountState,
+) -> QueryResult<(
+    ton_block::ShardStateUnsplit,
+    Option<ton_block::ShardAccount>,
+)> {
+    if !is_same_block(&response.id, mc_block_id) {
+        return Err(QueryError::AccountStateProofMismatch);
+    }
+
+    if !is_same_block(&response.shardblk, mc_block_id) {
+        check_shard_block_proof(mc_block_id, &response.shardblk, &response.shard_proof.0)?;
+    }
+
+    let roots = deserialize_roots(&response.proof.0)?;
+    if roots.len() != 2 {
+        return Err(QueryError::InvalidAccountStateProof);
+    }
+
+    let shard_state = check_state_proof(&response.shardblk, &roots[0], &roots[1])?;
+
+    let shard_account = shard_state
+        .read_accounts()
+        .and_then(|accounts| accounts.get(&UInt256::from(address.get_address().get_bytestring(0))))
+        .map_err(|_| QueryError::InvalidAccountStateProof)?;
+
+    let state_hash = if response.state.0.is_empty() {
+        None
+    } else {
+        Some(deserialize_root(&response.state.0)?.repr_hash())
+    };
+
+    match (&shard_account, state_hash) {
+        (Some(shard_account), Some(state_hash))
+            if shard_account.account_cell().repr_hash() == state_hash => {}
+        (None, None) => {}
+        _ => return Err(QueryError::AccountStateProofMismatch),
+    }
+
+    Ok((shard_state, shard_account))
+}
+
+/// Verifies the masterchain state proof returned by `GetConfigAll`/`GetConfigParams`
+/// and extracts the blockchain config from it
+pub fn check_config_proof(
+    mc_block_id: &BlockIdExt,
+    response: &ton::lite_server::configinfo::ConfigInfo,
+) -> QueryResult<ton_block::ConfigParams> {
+    if !is_same_block(&response.id, mc_block_id) {
+        return Err(QueryError::ConfigProofMismatch);
+    }
+
+    let block_proof = deserialize_root(&response.state_proof.0).map_err(into_config_proof_error)?;
+    let state_proof =
+        deserialize_root(&response.config_proof.0).map_err(into_config_proof_error)?;
+
+    check_state_proof(mc_block_id, &block_proof, &state_proof)
+        .map_err(into_config_proof_error)?
+        .read_custom()
+        .map_err(|_| QueryError::InvalidConfigProof)?
+        .map(|mc_state_extra| mc_state_extra.config)
+        .ok_or(QueryError::InvalidConfigProof)
+}
+
+/// Common proof checks report account state errors, they are replaced
+/// with the config ones for the config proofs
+fn into_config_proof_error(error: QueryError) -> QueryError {
+    match error {
+        QueryError::InvalidAccountStateProof => QueryError::InvalidConfigProof,
+        QueryError::AccountStateProofMismatch => QueryError::ConfigProofMismatch,
+        error => error,
+    }
+}
+
+/// Verifies that the shard block is registered in the masterchain block state
+fn check_shard_block_proof(
+    mc_block_id: &BlockIdExt,
+    shard_block_id: &BlockIdExt,
+    shard_proof: &[u8],
+) -> QueryResult<()> {
+    let roots = deserialize_roots(shard_proof)?;
+    if roots.len() != 2 {
+        return Err(QueryError::InvalidAccountStateProof);
+    }
+
+    let mc_state = check_state_proof(mc_block_id, &roots[0], &roots[1])?;
+    let mc_state_extra = mc_state
+        .read_custom()
+        .map_err(|_| QueryError::InvalidAccountStateProof)?
+        .ok_or(QueryError::InvalidAccountStateProof)?;
+
+    let mut found = false;
+    mc_state_extra
+        .shards()
+        .iterate_shards(|ident, descr| {
+            if ident.workchain_id() != shard_block_id.workchain
+                || ident.shard_prefix_with_tag() != shard_block_id.shard as u64
+            {
+                return Ok(true);
+            }
+
+            found = descr.seq_no == shard_block_id.seqno as u32
+                && descr.root_hash == UInt256::from(shard_block_id.root_hash.0)
+                && descr.file_hash == UInt256::from(shard_block_id.file_hash.0);
+            Ok(false)
+        })
+        .map_err(|_| QueryError::InvalidAccountStateProof)?;
+
+    if found {
+        Ok(())
+    } else {
+        Err(QueryError::AccountStateProofMismatch)
+    }
+}
+
+/// Verifies the block header proof against the block id and
+/// the state proof against the new state hash from that block
+fn check_state_proof(
+    block_id: &BlockIdExt,
+    block_proof: &Cell,
+    state_proof: &Cell,
+) -> QueryResult<ton_block::ShardStateUnsplit> {
+    let block_proof = ton_block::MerkleProof::construct_from_cell(block_proof.clone())
+        .map_err(|_| QueryError::InvalidAccountStateProof)?;
+    if block_proof.hash != UInt256::from(block_id.root_hash.0) {
+        return Err(QueryError::AccountStateProofMismatch);
+    }
+
+    let block = ton_block::Block::construct_from(&mut block_proof.proof.virtualize(1).into())
+        .map_err(|_| QueryError::InvalidAccountStateProof)?;
+    let state_update = block
+        .read_state_update()
+        .map_err(|_| QueryError::InvalidAccountStateProof)?;
+
+    let state_proof = ton_block::MerkleProof::construct_from_cell(state_proof.clone())
+        .map_err(|_| QueryError::InvalidAccountStateProof)?;
+    if state_proof.hash != state_update.new_hash {
+        return Err(QueryError::AccountStateProofMismatch);
+    }
+
+    ton_block::ShardStateUnsplit::construct_from(&mut state_proof.proof.virtualize(1).into())
+        .map_err(|_| QueryError::InvalidAccountStateProof)
+}
+
+fn deserialize_roots(data: &[u8]) -> QueryResult<Vec<Cell>> {
+    ton_types::deserialize_cells_tree(&mut std::io::Cursor::new(data))
+        .map_err(|_| QueryError::InvalidAccountStateProof)
+}
+
+fn deserialize_root(data: &[u8]) -> QueryResult<Cell> {
+    ton_types::deserialize_tree_of_cells(&mut std::io::Cursor::new(data))
+        .map_err(|_| QueryError::InvalidAccountStateProof)
+}
+
+fn is_same_block(left: &BlockIdExt, right: &BlockIdExt) -> bool {
+    left.workchain == right.workchain
+        && left.shard == right.shard
+        && left.seqno == right.seqno
+        && left.root_hash.0 == right.root_hash.0
+        && left.file_hash.0 == right.file_hash.0
+}
@@ -0,0 +1,101 @@
+use ton_block::{Deserializable, Serializable};
+use ton_types::UInt256;
+
+use adnl_rpc_models::{DecodedMessage, DecodedTransaction, TransactionId};
+
+use super::errors::*;
+use super::executor::{convert_action_phase, convert_compute_phase, grams};
+
+/// Splits the BOC returned by `GetTransactions` and decodes each transaction
+pub fn decode_transactions(data: &[u8]) -> QueryResult<Vec<DecodedTransaction>> {
+    ton_types::deserialize_cells_tree(&mut std::io::Cursor::new(data))
+        .map_err(|_| QueryError::InvalidBlock)?
+        .into_iter()
+        .map(|cell| {
+            let hash = cell.repr_hash();
+            let transaction = ton_block::Transaction::construct_from_cell(cell)
+                .map_err(|_| QueryError::InvalidBlock)?;
+            decode_transaction(hash, &transaction)
+        })
+        .collect()
+}
+
+pub fn decode_transaction(
+    hash: UInt256,
+    transaction: &ton_block::Transaction,
+) -> QueryResult<DecodedTransaction> {
+    let description = transaction
+        .read_description()
+        .map_err(|_| QueryError::InvalidBlock)?;
+
+    let (compute_phase, action_phase, aborted) = match &description {
+        ton_block::TransactionDescr::Ordinary(description) => (
+            Some(convert_compute_phase(&description.compute_ph)),
+            description.action.as_ref().map(convert_action_phase),
+            description.aborted,
+        ),
+        ton_block::TransactionDescr::TickTock(description) => (
+            Some(convert_compute_phase(&description.compute_ph)),
+            description.action.as_ref().map(convert_action_phase),
+            description.aborted,
+        ),
+        _ => (None, None, false),
+    };
+
+    let in_message = transaction
+        .read_in_msg()
+        .map_err(|_| QueryError::InvalidBlock)?
+        .map(|message| decode_message(&message))
+        .transpose()?;
+
+    let mut out_messages = Vec::with_capacity(transaction.outmsg_cnt as usize);
+    transaction
+        .iterate_out_msgs(|message| {
+            out_messages.push(message);
+            Ok(true)
+        })
+        .map_err(|_| QueryError::InvalidBlock)?;
+    let out_messages = out_messages
+        .iter()
+        .map(decode_message)
+        .collect::<QueryResult<Vec<_>>>()?;
+
+    let prev_transaction_id = match transaction.prev_trans_lt {
+        0 => None,
+        lt => Some(TransactionId {
+            lt,
+            hash: transaction.prev_trans_hash,
+        }),
+    };
+
+    Ok(DecodedTransaction {
+        hash,
+        lt: transaction.lt,
+        utime: transaction.now,
+        prev_transaction_id,
+        in_message,
+        out_messages,
+        compute_phase,
+        action_phase,
+        total_fees: grams(&transaction.total_fees().grams),
+        aborted,
+    })
+}
+
+fn decode_message(message: &ton_block::Message) -> QueryResult<DecodedMessage> {
+    let hash = message
+        .serialize()
+        .map_err(|_| QueryError::FailedToSerialize)?
+        .repr_hash();
+
+    Ok(DecodedMessage {
+        hash,
+        src: message.src(),
+        dst: message.dst(),
+        value: message
+            .get_value()
+            .map(|value| grams(&value.grams))
+            .unwrap_or_default(),
+        body: message.body().map(|body| body.into_cell()),
+    })
+}
@@ -0,0 +1,472 @@
+use num_bigint::{BigInt, Sign};
+use num_traits::{One, ToPrimitive, Zero};
+use ton_types::{BuilderData, Cell, IBitstring, SliceData};
+
+use adnl_rpc_models::{GetMethodId, StackItem};
+
+use super::errors::*;
+
+/// Computes get-method id the same way as FunC compiler does
+pub fn compute_method_id(method: &GetMethodId) -> i64 {
+    match method {
+        GetMethodId::Id(id) => *id,
+        GetMethodId::Name(name) => (crc16(name.as_bytes()) as i64 & 0xffff) | 0x10000,
+    }
+}
+
+/// Serializes stack into the `VmStack` BOC, the first item is the bottom of the stack
+pub fn serialize_stack(items: &[StackItem]) -> QueryResult<Vec<u8>> {
+    let mut list = BuilderData::new();
+    for item in items {
+        let mut cons = BuilderData::new();
+        cons.append_reference(list)
+            .map_err(|_| QueryError::InvalidStack)?;
+        write_stack_value(&mut cons, item)?;
+        list = cons;
+    }
+
+    let mut stack = BuilderData::new();
+    stack
+        .append_bits(items.len(), 24)
+        .and_then(|stack| stack.append_builder(&list))
+        .map_err(|_| QueryError::InvalidStack)?;
+
+    ton_types::serialize_toc(&stack.into()).map_err(|_| QueryError::InvalidStack)
+}
+
+/// Deserializes the `VmStack` BOC, the first item is the bottom of the stack
+pub fn deserialize_stack(data: &[u8]) -> QueryResult<Vec<StackItem>> {
+    let cell = ton_types::deserialize_tree_of_cells(&mut std::io::Cursor::new(data))
+        .map_err(|_| QueryError::InvalidStack)?;
+    let mut slice = SliceData::from(cell);
+
+    let depth = slice
+        .get_next_int(24)
+        .map_err(|_| QueryError::InvalidStack)? as usize;
+
+    let mut items = Vec::with_capacity(depth);
+    for _ in 0..depth {
+        let rest = slice
+            .checked_drain_reference()
+            .map_err(|_| QueryError::InvalidStack)?;
+        items.push(read_stack_value(&mut slice)?);
+        slice = SliceData::from(rest);
+    }
+    items.reverse();
+
+    Ok(items)
+}
+
+fn write_stack_value(builder: &mut BuilderData, item: &StackItem) -> QueryResult<()> {
+    match item {
+        StackItem::Null => builder.append_u8(0x00),
+        StackItem::Int(value) => {
+            let value = parse_int(value)?;
+            match value.to_i64() {
+                Some(value) => builder.append_u8(0x01).and_then(|b| b.append_i64(value)),
+                None => {
+                    builder
+                        .append_bits(0x0100, 15)
+                        .map_err(|_| QueryError::InvalidStack)?;
+                    return write_int257(builder, &value);
+                }
+            }
+        }
+        StackItem::Nan => builder.append_u16(0x02ff),
+        StackItem::Cell(cell) => builder
+            .append_u8(0x03)
+            .and_then(|b| b.checked_append_reference(cell.clone())),
+        StackItem::Slice(cell) => builder
+            .append_u8(0x04)
+            .and_then(|b| b.checked_append_reference(cell.clone()))
+            .and_then(|b| b.append_bits(0, 10))
+            .and_then(|b| b.append_bits(cell.bit_length(), 10))
+            .and_then(|b| b.append_bits(0, 3))
+            .and_then(|b| b.append_bits(cell.references_count(), 3)),
+        StackItem::Builder(cell) => builder
+            .append_u8(0x05)
+            .and_then(|b| b.checked_append_reference(cell.clone())),
+        StackItem::Tuple(items) => {
+            builder
+                .append_u8(0x07)
+                .and_then(|b| b.append_u16(items.len() as u16))
+                .map_err(|_| QueryError::InvalidStack)?;
+            return write_tuple(builder, items);
+        }
+    }
+    .map(|_| ())
+    .map_err(|_| QueryError::InvalidStack)
+}
+
+fn read_stack_value(slice: &mut SliceData) -> QueryResult<StackItem> {
+    let tag = slice
+        .get_next_byte()
+        .map_err(|_| QueryError::InvalidStack)?;
+    Ok(match tag {
+        0x00 => StackItem::Null,
+        0x01 => {
+            let value = slice.get_next_u64().map_err(|_| QueryError::InvalidStack)? as i64;
+            StackItem::Int(value.to_string())
+        }
+        0x02 => match slice
+            .get_next_int(7)
+            .map_err(|_| QueryError::InvalidStack)?
+        {
+            0 => StackItem::Int(read_int257(slice)?.to_string()),
+            0x7f if slice.get_next_bit().map_err(|_| QueryError::InvalidStack)? => StackItem::Nan,
+            _ => return Err(QueryError::InvalidStack),
+        },
+        0x03 => StackItem::Cell(
+            slice
+                .checked_drain_reference()
+                .map_err(|_| QueryError::InvalidStack)?,
+        ),
+        0x04 => StackItem::Slice(read_cell_slice(slice)?),
+        0x05 => StackItem::Builder(
+            slice
+                .checked_drain_reference()
+                .map_err(|_| QueryError::InvalidStack)?,
+        ),
+        0x07 => {
+            let len = slice.get_next_u16().map_err(|_| QueryError::InvalidStack)? as usize;
+            StackItem::Tuple(read_tuple(slice, len)?)
+        }
+        // Continuations are not supported
+        _ => return Err(QueryError::InvalidStack),
+    })
+}
+
+/// `VmTuple n`: the first `n - 1` items are referenced by `VmTupleRef (n - 1)`,
+/// the last one is stored in a separate cell
+fn write_tuple(builder: &mut BuilderData, items: &[StackItem]) -> QueryResult<()> {
+    let (last, head) = match items.split_last() {
+        Some(items) => items,
+        None => return Ok(()),
+    };
+
+    write_tuple_ref(builder, head)?;
+
+    let mut tail = BuilderData::new();
+    write_stack_value(&mut tail, last)?;
+    builder
+        .append_reference(tail)
+        .map(|_| ())
+        .map_err(|_| QueryError::InvalidStack)
+}
+
+fn write_tuple_ref(builder: &mut BuilderData, items: &[StackItem]) -> QueryResult<()> {
+    let mut child = BuilderData::new();
+    match items {
+        [] => return Ok(()),
+        [item] => write_stack_value(&mut child, item)?,
+        items => write_tuple(&mut child, items)?,
+    }
+    builder
+        .append_reference(child)
+        .map(|_| ())
+        .map_err(|_| QueryError::InvalidStack)
+}
+
+fn read_tuple(slice: &mut SliceData, len: usize) -> QueryResult<Vec<StackItem>> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut items = read_tuple_ref(slice, len - 1)?;
+
+    let mut tail = SliceData::from(
+        slice
+            .checked_drain_reference()
+            .map_err(|_| QueryError::InvalidStack)?,
+    );
+    items.push(read_stack_value(&mut tail)?);
+
+    Ok(items)
+}
+
+fn read_tuple_ref(slice: &mut SliceData, len: usize) -> QueryResult<Vec<StackItem>> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut child = SliceData::from(
+        slice
+            .checked_drain_reference()
+            .map_err(|_| QueryError::InvalidStack)?,
+    );
+    if len == 1 {
+        Ok(vec![read_stack_value(&mut child)?])
+    } else {
+        read_tuple(&mut child, len)
+    }
+}
+
+/// `VmCellSlice`: cell with the bits and references range
+fn read_cell_slice(slice: &mut SliceData) -> QueryResult<Cell> {
+    let cell = slice
+        .checked_drain_reference()
+        .map_err(|_| QueryError::InvalidStack)?;
+
+    let mut read_int = |bits| {
+        slice
+            .get_next_int(bits)
+            .map(|value| value as usize)
+            .map_err(|_| QueryError::InvalidStack)
+    };
+    let st_bits = read_int(10)?;
+    let end_bits = read_int(10)?;
+    let st_ref = read_int(3)?;
+    let end_ref = read_int(3)?;
+    if st_bits > end_bits || st_ref > end_ref {
+        return Err(QueryError::InvalidStack);
+    }
+
+    let mut cell_slice = SliceData::from(cell);
+    cell_slice.shrink_data(st_bits..end_bits);
+    cell_slice.shrink_references(st_ref..end_ref);
+
+    Ok(cell_slice.into_cell())
+}
+
+fn parse_int(value: &str) -> QueryResult<BigInt> {
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+
+    let value = match value.strip_prefix("0x") {
+        Some(hex) => BigInt::parse_bytes(hex.as_bytes(), 16),
+        None => BigInt::parse_bytes(value.as_bytes(), 10),
+    }
+    .ok_or(QueryError::InvalidStack)?;
+
+    Ok(if negative { -value } else { value })
+}
+
+fn write_int257(builder: &mut BuilderData, value: &BigInt) -> QueryResult<()> {
+    let modulus = BigInt::one() << 256;
+
+    // Two's complement representation of the lower 256 bits
+    let (sign, low) = if value < &BigInt::zero() {
+        (1, value + &modulus)
+    } else {
+        (0, value.clone())
+    };
+    if low < BigInt::zero() || low >= modulus {
+        return Err(QueryError::InvalidStack);
+    }
+
+    let (_, bytes) = low.to_bytes_be();
+    let mut data = [0u8; 32];
+    data[32 - bytes.len()..].copy_from_slice(&bytes);
+
+    builder
+        .append_bits(sign, 1)
+        .and_then(|b| b.append_raw(&data, 256))
+        .map(|_| ())
+        .map_err(|_| QueryError::InvalidStack)
+}
+
+fn read_int257(slice: &mut SliceData) -> QueryResult<BigInt> {
+    let negative = slice.get_next_bit().map_err(|_| QueryError::InvalidStack)?;
+    let data = slice
+        .get_next_bytes(32)
+        .map_err(|_| QueryError::InvalidStack)?;
+
+    let value = BigInt::from_bytes_be(Sign::Plus, &data);
+    Ok(if negative {
+        value - (BigInt::one() << 256)
+    } else {
+        value
+    })
+}
+
+/// CRC16/XMODEM
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_INT257: &str =
+        "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+    const MIN_INT257: &str =
+        "-115792089237316195423570985008687907853269984665640564039457584007913129639936";
+
+    fn to_json(items: &[StackItem]) -> serde_json::Value {
+        serde_json::to_value(items).unwrap()
+    }
+
+    fn round_trip(items: Vec<StackItem>) {
+        let data = serialize_stack(&items).unwrap();
+        let decoded = deserialize_stack(&data).unwrap();
+        assert_eq!(to_json(&decoded), to_json(&items));
+    }
+
+    fn int(value: &str) -> StackItem {
+        StackItem::Int(value.to_owned())
+    }
+
+    fn cell(data: u32) -> Cell {
+        let mut builder = BuilderData::new();
+        builder.append_u32(data).unwrap();
+        builder.into()
+    }
+
+    #[test]
+    fn method_id() {
+        assert_eq!(
+            compute_method_id(&GetMethodId::Name("seqno".to_owned())),
+            85143
+        );
+        assert_eq!(compute_method_id(&GetMethodId::Id(123)), 123);
+    }
+
+    #[test]
+    fn tiny_ints() {
+        round_trip(vec![
+            int("0"),
+            int("-1"),
+            int(&i64::MAX.to_string()),
+            int(&i64::MIN.to_string()),
+        ]);
+    }
+
+    #[test]
+    fn big_ints() {
+        round_trip(vec![
+            int("9223372036854775808"),
+            int("-9223372036854775809"),
+            int(MAX_INT257),
+            int(MIN_INT257),
+        ]);
+    }
+
+    #[test]
+    fn int_overflow() {
+        let too_big =
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+        let too_small =
+            "-115792089237316195423570985008687907853269984665640564039457584007913129639937";
+
+        assert!(serialize_stack(&[int(too_big)]).is_err());
+        assert!(serialize_stack(&[int(too_small)]).is_err());
+    }
+
+    #[test]
+    fn hex_ints() {
+        let data = serialize_stack(&[int("0xff"), int("-0x10")]).unwrap();
+        let decoded = deserialize_stack(&data).unwrap();
+        assert_eq!(to_json(&decoded), to_json(&[int("255"), int("-16")]));
+    }
+
+    #[test]
+    fn nan_and_null() {
+        round_trip(vec![StackItem::Nan, StackItem::Null, StackItem::Nan]);
+    }
+
+    #[test]
+    fn cells() {
+        round_trip(vec![
+            StackItem::Cell(cell(1)),
+            StackItem::Slice(cell(2)),
+            StackItem::Builder(cell(3)),
+        ]);
+    }
+
+    #[test]
+    fn tuples() {
+        round_trip(vec![StackItem::Tuple(Vec::new())]);
+        round_trip(vec![StackItem::Tuple(vec![int("1")])]);
+        round_trip(vec![StackItem::Tuple(vec![int("1"), StackItem::Nan])]);
+        round_trip(vec![StackItem::Tuple(vec![
+            int("1"),
+            StackItem::Cell(cell(2)),
+            int(MAX_INT257),
+            StackItem::Null,
+        ])]);
+    }
+
+    #[test]
+    fn nested_tuples() {
+        round_trip(vec![StackItem::Tuple(vec![
+            StackItem::Tuple(Vec::new()),
+            StackItem::Tuple(vec![int("1")]),
+            StackItem::Tuple(vec![int("2"), StackItem::Tuple(vec![int("3"), int("4")])]),
+            StackItem::Tuple(vec![int("5"), int("6"), int("7")]),
+        ])]);
+    }
+
+    #[test]
+    fn slice_range() {
+        let mut first_ref = BuilderData::new();
+        first_ref.append_u8(1).unwrap();
+        let mut second_ref = BuilderData::new();
+        second_ref.append_u8(2).unwrap();
+
+        let mut data = BuilderData::new();
+        data.append_u32(0xdeadbeef)
+            .and_then(|b| b.append_reference(first_ref))
+            .and_then(|b| b.append_reference(second_ref.clone()))
+            .unwrap();
+        let data: Cell = data.into();
+
+        // Stack with one slice of bits 8..24 and references 1..2
+        let mut stack = BuilderData::new();
+        stack
+            .append_bits(1, 24)
+            .and_then(|b| b.append_reference(BuilderData::new()))
+            .and_then(|b| b.append_u8(0x04))
+            .and_then(|b| b.checked_append_reference(data))
+            .and_then(|b| b.append_bits(8, 10))
+            .and_then(|b| b.append_bits(24, 10))
+            .and_then(|b| b.append_bits(1, 3))
+            .and_then(|b| b.append_bits(2, 3))
+            .unwrap();
+        let stack = ton_types::serialize_toc(&stack.into()).unwrap();
+
+        let mut expected = BuilderData::new();
+        expected
+            .append_u16(0xadbe)
+            .and_then(|b| b.append_reference(second_ref))
+            .unwrap();
+        let expected: Cell = expected.into();
+
+        match deserialize_stack(&stack).unwrap().as_slice() {
+            [StackItem::Slice(slice)] => assert_eq!(slice.repr_hash(), expected.repr_hash()),
+            items => panic!("Unexpected stack: {:?}", items),
+        }
+    }
+
+    #[test]
+    fn invalid_slice_range() {
+        let mut stack = BuilderData::new();
+        stack
+            .append_bits(1, 24)
+            .and_then(|b| b.append_reference(BuilderData::new()))
+            .and_then(|b| b.append_u8(0x04))
+            .and_then(|b| b.checked_append_reference(cell(0)))
+            .and_then(|b| b.append_bits(16, 10))
+            .and_then(|b| b.append_bits(8, 10))
+            .and_then(|b| b.append_bits(0, 3))
+            .and_then(|b| b.append_bits(0, 3))
+            .unwrap();
+        let stack = ton_types::serialize_toc(&stack.into()).unwrap();
+
+        assert!(deserialize_stack(&stack).is_err());
+    }
+}
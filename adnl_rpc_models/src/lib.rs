@@ -17,6 +17,25 @@ pub struct SendMessage {
     pub message: ton_block::Message,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendMessageAndWait {
+    #[serde(with = "serde_ton_block")]
+    pub message: ton_block::Message,
+    /// ABI of the destination contract, used to read the `expire` header of the message
+    pub abi: Option<String>,
+    /// Expiration timestamp which is used if the message has no `expire` header
+    pub expire_at: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendMessageAndWaitResponse {
+    #[serde(with = "serde_uint256")]
+    pub message_hash: UInt256,
+    pub transaction: RawTransaction,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetTransactions {
@@ -26,6 +45,359 @@ pub struct GetTransactions {
     pub count: u8,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GetTransaction {
+    /// Transaction with the specified lt in the known block
+    #[serde(rename_all = "camelCase")]
+    ByBlock {
+        #[serde(with = "serde_address")]
+        address: ton_block::MsgAddressInt,
+        #[serde(with = "serde_u64")]
+        lt: u64,
+        block_id: BlockIdExt,
+    },
+    #[serde(rename_all = "camelCase")]
+    ById {
+        #[serde(with = "serde_address")]
+        address: ton_block::MsgAddressInt,
+        transaction_id: TransactionId,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionWithBlockId {
+    pub block_id: BlockIdExt,
+    pub transaction: RawTransaction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunGetMethod {
+    #[serde(with = "serde_address")]
+    pub address: ton_block::MsgAddressInt,
+    pub method: GetMethodId,
+    #[serde(default)]
+    pub stack: Vec<StackItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GetMethodId {
+    Id(i64),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunGetMethodResponse {
+    pub exit_code: i32,
+    pub stack: Vec<StackItem>,
+}
+
+/// TVM stack entry. Integers are represented as decimal strings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type", content = "value")]
+pub enum StackItem {
+    Null,
+    Int(String),
+    Nan,
+    Cell(#[serde(with = "serde_cell")] ton_types::Cell),
+    Slice(#[serde(with = "serde_cell")] ton_types::Cell),
+    Builder(#[serde(with = "serde_cell")] ton_types::Cell),
+    Tuple(Vec<StackItem>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunLocal {
+    #[serde(with = "serde_address")]
+    pub address: ton_block::MsgAddressInt,
+    /// Contract ABI JSON
+    pub abi: String,
+    pub method: String,
+    /// Function inputs as an object, `answerId` is filled automatically if omitted
+    #[serde(default)]
+    pub input: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunLocalResponse {
+    pub aborted: bool,
+    /// TVM exit code
+    pub exit_code: i32,
+    /// Decoded function outputs, `None` if no answer was produced
+    pub output: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmulateMessageResponse {
+    pub aborted: bool,
+    pub compute_phase: ComputePhase,
+    pub action_phase: Option<ActionPhase>,
+    #[serde(with = "serde_u128")]
+    pub total_fees: u128,
+    pub out_messages: Vec<RawMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum ComputePhase {
+    #[serde(rename_all = "camelCase")]
+    Skipped { reason: ComputeSkipReason },
+    #[serde(rename_all = "camelCase")]
+    Vm {
+        success: bool,
+        exit_code: i32,
+        #[serde(with = "serde_u128")]
+        gas_fees: u128,
+        #[serde(with = "serde_u64")]
+        gas_used: u64,
+        vm_steps: u32,
+    },
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ComputeSkipReason {
+    NoState,
+    BadState,
+    NoGas,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionPhase {
+    pub success: bool,
+    pub valid: bool,
+    pub no_funds: bool,
+    pub result_code: i32,
+    #[serde(with = "serde_u128")]
+    pub total_fwd_fees: u128,
+    #[serde(with = "serde_u128")]
+    pub total_action_fees: u128,
+    pub total_actions: i16,
+    pub messages_created: i16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupBlockBySeqno {
+    pub workchain: i32,
+    #[serde(with = "serde_hex_u64")]
+    pub shard: u64,
+    pub seqno: u32,
+    #[serde(default)]
+    pub with_block: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupBlockByLt {
+    pub workchain: i32,
+    #[serde(with = "serde_hex_u64")]
+    pub shard: u64,
+    #[serde(with = "serde_u64")]
+    pub lt: u64,
+    #[serde(default)]
+    pub with_block: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupBlockByUtime {
+    pub workchain: i32,
+    #[serde(with = "serde_hex_u64")]
+    pub shard: u64,
+    pub utime: u32,
+    #[serde(default)]
+    pub with_block: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LookupBlockResponse {
+    pub id: BlockIdExt,
+    /// Full block, present only if `withBlock` was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<RawBlock>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetConfigParams {
+    #[serde(default)]
+    pub source: ConfigSource,
+    /// Params to decode. The whole config is returned as is if omitted
+    pub params: Option<Vec<u32>>,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConfigSource {
+    /// Config from the latest key block cached by the server
+    KeyBlock,
+    /// Config from the latest masterchain state, requested from the lite server
+    LiteServer,
+}
+
+impl Default for ConfigSource {
+    fn default() -> Self {
+        Self::KeyBlock
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum GetConfigParamsResponse {
+    Raw {
+        #[serde(with = "serde_cell")]
+        config: ton_types::Cell,
+    },
+    Decoded {
+        params: std::collections::BTreeMap<u32, ConfigParam>,
+    },
+}
+
+/// Config param decoded to JSON. Params without the known structure are returned as cells
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type", content = "value")]
+pub enum ConfigParam {
+    Workchains(Vec<WorkchainDescription>),
+    GasPrices(GasPrices),
+    MsgForwardPrices(MsgForwardPrices),
+    ValidatorSet(ValidatorSet),
+    Raw(#[serde(with = "serde_cell")] ton_types::Cell),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkchainDescription {
+    pub workchain: i32,
+    pub enabled_since: u32,
+    pub actual_min_split: u8,
+    pub min_split: u8,
+    pub max_split: u8,
+    pub active: bool,
+    pub accept_msgs: bool,
+    #[serde(with = "serde_uint256")]
+    pub zerostate_root_hash: UInt256,
+    #[serde(with = "serde_uint256")]
+    pub zerostate_file_hash: UInt256,
+    pub version: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasPrices {
+    #[serde(with = "serde_u64")]
+    pub gas_price: u64,
+    #[serde(with = "serde_u64")]
+    pub gas_limit: u64,
+    #[serde(with = "serde_u64")]
+    pub special_gas_limit: u64,
+    #[serde(with = "serde_u64")]
+    pub gas_credit: u64,
+    #[serde(with = "serde_u64")]
+    pub block_gas_limit: u64,
+    #[serde(with = "serde_u64")]
+    pub freeze_due_limit: u64,
+    #[serde(with = "serde_u64")]
+    pub delete_due_limit: u64,
+    #[serde(with = "serde_u64")]
+    pub flat_gas_limit: u64,
+    #[serde(with = "serde_u64")]
+    pub flat_gas_price: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgForwardPrices {
+    #[serde(with = "serde_u64")]
+    pub lump_price: u64,
+    #[serde(with = "serde_u64")]
+    pub bit_price: u64,
+    #[serde(with = "serde_u64")]
+    pub cell_price: u64,
+    pub ihr_price_factor: u32,
+    pub first_frac: u16,
+    pub next_frac: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatorSet {
+    pub utime_since: u32,
+    pub utime_until: u32,
+    pub total: u16,
+    pub main: u16,
+    #[serde(with = "serde_u64")]
+    pub total_weight: u64,
+    pub validators: Vec<ValidatorDescription>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidatorDescription {
+    /// Hex encoded ed25519 public key
+    pub public_key: String,
+    #[serde(with = "serde_u64")]
+    pub weight: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adnl_addr: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBlockTransactions {
+    pub id: BlockIdExt,
+    /// Transactions are listed after this one
+    pub after: Option<BlockTransactionCursor>,
+    /// Max number of transactions, at most 256 are returned
+    pub count: u32,
+    /// Whether to include transaction BOCs
+    #[serde(default)]
+    pub with_data: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockTransactionCursor {
+    #[serde(with = "serde_address")]
+    pub account: ton_block::MsgAddressInt,
+    #[serde(with = "serde_u64")]
+    pub lt: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetBlockTransactionsResponse {
+    pub id: BlockIdExt,
+    /// Whether there are more transactions after the returned ones
+    pub incomplete: bool,
+    pub transactions: Vec<BlockTransaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockTransaction {
+    #[serde(with = "serde_address")]
+    pub account: ton_block::MsgAddressInt,
+    #[serde(with = "serde_u64")]
+    pub lt: u64,
+    #[serde(with = "serde_uint256")]
+    pub hash: UInt256,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_optional_cell"
+    )]
+    pub data: Option<ton_types::Cell>,
+}
+
 #[derive(Debug, Copy, Clone, Eq, Serialize, Deserialize)]
 pub struct TransactionId {
     #[serde(with = "serde_u64")]
@@ -61,8 +433,22 @@ pub enum WsRequestMessage {
         #[serde(with = "serde_address")]
         address: ton_block::MsgAddressInt,
     },
+    SubscribeForNewBlock(Option<NewBlockSubscription>),
     #[serde(rename_all = "camelCase")]
-    SubscribeForNewBlock,
+    UnsubscribeAccount {
+        #[serde(with = "serde_address")]
+        address: ton_block::MsgAddressInt,
+    },
+    UnsubscribeFromNewBlock,
+    UnsubscribeAll,
+}
+
+#[derive(Debug, Copy, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewBlockSubscription {
+    /// Whether to include ids of the shard blocks committed in the masterchain block
+    #[serde(default)]
+    pub with_shards: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -70,7 +456,92 @@ pub enum WsRequestMessage {
 #[serde(tag = "messageType", content = "payload")]
 pub enum WsResponseMessage {
     Transaction(serde_json::Value),
-    Block {},
+    Block(NewBlock),
+    Subscribed(Subscription),
+    Unsubscribed(Subscription),
+    SubscriptionsLimitReached(Subscription),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Subscription {
+    #[serde(rename_all = "camelCase")]
+    Account {
+        #[serde(with = "serde_address")]
+        address: ton_block::MsgAddressInt,
+    },
+    NewBlock,
+    All,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewBlock {
+    pub id: BlockIdExt,
+    pub gen_utime: u32,
+    pub is_key_block: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shard_block_ids: Option<Vec<BlockIdExt>>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockIdExt {
+    pub workchain: i32,
+    #[serde(with = "serde_hex_u64")]
+    pub shard: u64,
+    pub seqno: u32,
+    #[serde(with = "serde_uint256")]
+    pub root_hash: UInt256,
+    #[serde(with = "serde_uint256")]
+    pub file_hash: UInt256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MasterchainInfo {
+    pub last: BlockIdExt,
+    #[serde(with = "serde_uint256")]
+    pub state_root_hash: UInt256,
+    pub init: ZeroStateIdExt,
+    /// Seconds between now and the generation time of the last block seen by the server
+    pub time_diff: u32,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZeroStateIdExt {
+    pub workchain: i32,
+    #[serde(with = "serde_uint256")]
+    pub root_hash: UInt256,
+    #[serde(with = "serde_uint256")]
+    pub file_hash: UInt256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetShardsResponse {
+    pub mc_block_id: BlockIdExt,
+    pub workchains: Vec<WorkchainShards>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkchainShards {
+    pub workchain: i32,
+    pub shards: Vec<ShardDescription>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShardDescription {
+    /// Id of the top shard block
+    pub id: BlockIdExt,
+    pub gen_utime: u32,
+    pub before_split: bool,
+    pub before_merge: bool,
+    pub want_split: bool,
+    pub want_merge: bool,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -104,12 +575,102 @@ pub struct RawTransactionsList {
     pub transactions: Vec<u8>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedTransactionsList {
+    pub transactions: Vec<DecodedTransaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedTransaction {
+    #[serde(with = "serde_uint256")]
+    pub hash: UInt256,
+    #[serde(with = "serde_u64")]
+    pub lt: u64,
+    pub utime: u32,
+    /// Id of the previous transaction of the account, `None` for the first one
+    pub prev_transaction_id: Option<TransactionId>,
+    pub in_message: Option<DecodedMessage>,
+    pub out_messages: Vec<DecodedMessage>,
+    /// `None` for the transactions without the compute phase
+    pub compute_phase: Option<ComputePhase>,
+    pub action_phase: Option<ActionPhase>,
+    #[serde(with = "serde_u128")]
+    pub total_fees: u128,
+    pub aborted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedMessage {
+    #[serde(with = "serde_uint256")]
+    pub hash: UInt256,
+    /// `None` for external inbound messages
+    #[serde(with = "serde_optional_address")]
+    pub src: Option<ton_block::MsgAddressInt>,
+    /// `None` for external outbound messages
+    #[serde(with = "serde_optional_address")]
+    pub dst: Option<ton_block::MsgAddressInt>,
+    #[serde(with = "serde_u128")]
+    pub value: u128,
+    #[serde(with = "serde_optional_cell")]
+    pub body: Option<ton_types::Cell>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawTransaction {
+    #[serde(with = "serde_uint256")]
+    pub hash: UInt256,
+    #[serde(with = "serde_ton_block")]
+    pub data: ton_block::Transaction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawMessage {
+    #[serde(with = "serde_uint256")]
+    pub hash: UInt256,
+    #[serde(with = "serde_ton_block")]
+    pub data: ton_block::Message,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawBlock {
     #[serde(with = "serde_ton_block")]
     pub block: ton_block::Block,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthcheckResponse {
+    pub ok: bool,
+    /// Current server time in milliseconds
+    pub timestamp: i64,
+    pub time_diff: u32,
+    pub max_time_diff: u32,
+    pub max_unreliability: usize,
+    pub last_block: Option<LastBlockStatus>,
+    pub servers: Vec<LiteServerStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LastBlockStatus {
+    pub seqno: u32,
+    /// Milliseconds since the block was generated
+    #[serde(with = "serde_u64")]
+    pub age_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LiteServerStatus {
+    pub address: String,
+    pub ok: bool,
+    pub unreliability: usize,
+    pub connections: u32,
+    pub idle_connections: u32,
+}
+
 pub mod serde_u64 {
     use serde::de::Error;
     use serde::Deserialize;
@@ -132,6 +693,48 @@ pub mod serde_u64 {
     }
 }
 
+pub mod serde_u128 {
+    use serde::de::Error;
+    use serde::Deserialize;
+
+    use super::*;
+
+    pub fn serialize<S>(data: &u128, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        data.to_string().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u128, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer)
+            .and_then(|data| u128::from_str(&data).map_err(D::Error::custom))
+    }
+}
+
+pub mod serde_hex_u64 {
+    use serde::de::Error;
+    use serde::Deserialize;
+
+    pub fn serialize<S>(data: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&format!("{:016x}", data))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer)
+            .and_then(|data| u64::from_str_radix(&data, 16).map_err(D::Error::custom))
+    }
+}
+
 pub mod serde_uint256 {
     use serde::de::Error;
     use serde::Deserialize;
@@ -310,6 +913,37 @@ pub mod serde_cell {
     }
 }
 
+pub mod serde_optional_cell {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    pub fn serialize<S>(data: &Option<ton_types::Cell>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        #[derive(Serialize)]
+        #[serde(transparent)]
+        struct Wrapper<'a>(#[serde(with = "serde_cell")] &'a ton_types::Cell);
+
+        match data {
+            Some(data) => serializer.serialize_some(&Wrapper(data)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<ton_types::Cell>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(transparent)]
+        struct Wrapper(#[serde(with = "serde_cell")] ton_types::Cell);
+
+        Option::<Wrapper>::deserialize(deserializer).map(|wrapper| wrapper.map(|data| data.0))
+    }
+}
+
 pub mod serde_bytes {
     use super::*;
 
@@ -330,3 +964,55 @@ pub mod serde_bytes {
         base64::decode(&data).map_err(D::Error::custom)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct HexU64(#[serde(with = "serde_hex_u64")] u64);
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct U128(#[serde(with = "serde_u128")] u128);
+
+    #[test]
+    fn hex_u64_round_trip() {
+        let cases = [
+            (0, "0000000000000000"),
+            (0x8000000000000000, "8000000000000000"),
+            (u64::MAX, "ffffffffffffffff"),
+        ];
+        for (value, hex) in cases.iter() {
+            let json = serde_json::to_string(&HexU64(*value)).unwrap();
+            assert_eq!(json, format!("\"{}\"", hex));
+            assert_eq!(
+                serde_json::from_str::<HexU64>(&json).unwrap(),
+                HexU64(*value)
+            );
+        }
+    }
+
+    #[test]
+    fn hex_u64_invalid() {
+        assert!(serde_json::from_str::<HexU64>("\"1ffffffffffffffff\"").is_err());
+        assert!(serde_json::from_str::<HexU64>("\"xyz\"").is_err());
+    }
+
+    #[test]
+    fn u128_round_trip() {
+        for value in [0, u64::MAX as u128 + 1, u128::MAX].iter() {
+            let json = serde_json::to_string(&U128(*value)).unwrap();
+            assert_eq!(json, format!("\"{}\"", value));
+            assert_eq!(serde_json::from_str::<U128>(&json).unwrap(), U128(*value));
+        }
+    }
+
+    #[test]
+    fn u128_invalid() {
+        assert!(serde_json::from_str::<U128>("\"-1\"").is_err());
+        assert!(
+            serde_json::from_str::<U128>("\"340282366920938463463374607431768211456\"").is_err()
+        );
+        assert!(serde_json::from_str::<U128>("1").is_err());
+    }
+}